@@ -0,0 +1,45 @@
+use crate::database::{Decision, DecisionQuery};
+
+/// The subcommand cross-seed was started with.
+///
+/// Any flags that aren't used by a subcommand are still read as config options.
+pub enum Command {
+    /// Search for cross-seeds using the configured run mode.
+    Run,
+    /// Print the decision log, e.g. `cross-seed decisions --name "Some Show" --decision injected --limit 20`
+    Decisions(DecisionQuery),
+    /// Search for a single torrent by its info hash, e.g. `cross-seed search --infohash <hash>`.
+    /// This can be run by the client when a torrent finishes downloading.
+    Search(String),
+}
+
+impl Command {
+    /// Parse the subcommand from the command line arguments. Returns the usage of the subcommand if it's
+    /// missing a required flag.
+    pub fn from_args() -> Result<Self, &'static str> {
+        let args: Vec<String> = wild::args().collect();
+        let (positional, flags) = argmap::parse(args.iter());
+
+        let flag = |key: &str| flags.get(key)
+            .and_then(|vals| vals.first())
+            .cloned();
+
+        // The first positional argument is the path of the binary.
+        let command = match positional.get(1).map(|s| s.as_str()) {
+            Some("decisions") => Command::Decisions(DecisionQuery {
+                name: flag("name"),
+                info_hash: flag("infohash"),
+                indexer: flag("indexer"),
+                decision: flag("decision").and_then(|decision| Decision::parse(&decision)),
+                limit: flag("limit").and_then(|limit| limit.parse().ok()),
+            }),
+            Some("search") => match flag("infohash") {
+                Some(info_hash) => Command::Search(info_hash),
+                None => return Err("cross-seed search --infohash <hash>"),
+            },
+            _ => Command::Run,
+        };
+
+        Ok(command)
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use crate::library::ClientState;
+use crate::torrent_client::{ClientResult, TorrentClients};
+
+/// Watches the torrent lists of the clients for torrents that finished downloading.
+pub struct CompletionWatcher {
+    torrent_clients: Arc<TorrentClients>,
+    /// Info hashes of the torrents that were seeding at the last poll. This is `None`
+    /// until the first poll, so torrents that were already seeding aren't reported.
+    seeding: Mutex<Option<HashSet<String>>>,
+}
+
+impl CompletionWatcher {
+    pub fn new(torrent_clients: Arc<TorrentClients>) -> Self {
+        Self {
+            torrent_clients,
+            seeding: Mutex::new(None),
+        }
+    }
+
+    /// Poll the torrent lists of the clients, returning the info hashes of the torrents
+    /// that moved to a seeding state since the last poll.
+    pub async fn poll(&self) -> ClientResult<Vec<String>> {
+        // The poll also keeps the snapshots of the clients fresh for the searches it queues.
+        let torrents = self.torrent_clients.refresh().await?;
+
+        let current: HashSet<String> = torrents.iter()
+            .filter(|info| ClientState::from_info(Some(info)) == ClientState::Complete)
+            .map(|info| info.hash.to_lowercase())
+            .collect();
+
+        let mut seeding = self.seeding.lock().await;
+        let completed = match seeding.as_ref() {
+            Some(previous) => current.difference(previous).cloned().collect(),
+            None => {
+                debug!("Watching {} seeding torrents for completion", current.len());
+                vec![]
+            }
+        };
+        *seeding = Some(current);
+
+        Ok(completed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use crate::torrent_client::{TorrentBackend, TorrentClient, TorrentFile, TorrentInfo, TorrentState, TorrentUpload};
+
+    use super::*;
+
+    /// A client whose torrents are changed by the test between polls.
+    #[derive(Default)]
+    struct StandInBackend {
+        torrents: std::sync::Mutex<Vec<TorrentInfo>>,
+    }
+
+    impl StandInBackend {
+        fn set(&self, torrents: &[(&str, TorrentState)]) {
+            *self.torrents.lock().unwrap() = torrents.iter()
+                .map(|(hash, state)| TorrentInfo { state: *state, ..TorrentInfo::from_hash(hash.to_string()) })
+                .collect();
+        }
+    }
+
+    #[async_trait]
+    impl TorrentBackend for Arc<StandInBackend> {
+        async fn login(&mut self) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+            Ok(self.torrents.lock().unwrap().clone())
+        }
+
+        async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+            Ok(self.torrents.lock().unwrap().iter().find(|info| info.hash == hash).cloned())
+        }
+
+        async fn get_torrent_trackers(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+            Ok(vec![])
+        }
+
+        async fn get_torrent_files(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+            Ok(vec![])
+        }
+
+        async fn get_torrent_progress(&self, _hash: &str) -> ClientResult<Option<f64>> {
+            Ok(None)
+        }
+
+        async fn add_torrent_trackers(&self, _torrent: &TorrentInfo, _trackers: Vec<String>) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn recheck_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn resume_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn add_torrent(&self, _upload: &TorrentUpload) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn remove_torrent(&self, _torrent: &TorrentInfo, _delete_files: bool) -> ClientResult<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_each_completed_torrent_once() {
+        let backend = Arc::new(StandInBackend::default());
+        let client = TorrentClient::new(String::from("stand-in"), Box::new(backend.clone()));
+        let watcher = CompletionWatcher::new(Arc::new(TorrentClients::new(vec![client]).unwrap()));
+
+        // Torrents that were seeding before the first poll aren't reported.
+        backend.set(&[("aa", TorrentState::Uploading), ("bb", TorrentState::Downloading), ("cc", TorrentState::Downloading)]);
+        assert!(watcher.poll().await.unwrap().is_empty());
+
+        backend.set(&[("aa", TorrentState::Uploading), ("bb", TorrentState::QueuedUploading), ("cc", TorrentState::Downloading)]);
+        assert_eq!(watcher.poll().await.unwrap(), vec!["bb"]);
+        assert!(watcher.poll().await.unwrap().is_empty());
+
+        // A torrent that's rechecked and seeds again is reported again, a paused one isn't.
+        backend.set(&[("aa", TorrentState::CheckingUploading), ("bb", TorrentState::Uploading), ("cc", TorrentState::PausedUploading)]);
+        assert!(watcher.poll().await.unwrap().is_empty());
+        backend.set(&[("aa", TorrentState::Uploading), ("bb", TorrentState::Uploading), ("cc", TorrentState::PausedUploading)]);
+        assert_eq!(watcher.poll().await.unwrap(), vec!["aa"]);
+    }
+}
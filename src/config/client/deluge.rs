@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DelugeConfig {
+    /// Url of the deluge web ui. Ex: `http://localhost:8112`
+    pub url: String,
+    /// Password of the web ui.
+    pub password: String,
+    /// Id of the daemon host the web ui should connect to, if it isn't connected already.
+    /// The first host is used when this isn't set.
+    pub host: Option<String>,
+}
@@ -1 +1,62 @@
-pub mod qbittorrent;
\ No newline at end of file
+pub mod qbittorrent;
+pub mod transmission;
+pub mod deluge;
+pub mod rtorrent;
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// A named torrent client in the `clients` list.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClientConfig {
+    /// Name of the client, used in logs and to tell clients of the same type apart.
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ClientKind,
+    /// Translations between the paths the client reports and the paths cross-seed sees, for clients
+    /// that see the files at another path, ex: through docker volumes.
+    #[serde(default)]
+    pub path_mappings: Vec<PathMapping>,
+    /// Route the requests to the client through the global proxy. Off by default, since clients are usually
+    /// reached on the local network.
+    #[serde(default)]
+    pub proxy: bool,
+}
+
+/// A path prefix as the client sees it, and the same path as cross-seed sees it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PathMapping {
+    /// Ex: `/downloads`
+    pub client: PathBuf,
+    /// Ex: `/mnt/media/downloads`
+    pub local: PathBuf,
+}
+
+impl PathMapping {
+    /// Translate a path the client reports to the path cross-seed sees, `None` if this mapping doesn't apply.
+    pub fn to_local(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.client).ok().map(|rest| self.local.join(rest))
+    }
+}
+
+/// The type of a torrent client with its connection settings, ex: `type: qbittorrent`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientKind {
+    QBittorrent(qbittorrent::QBittorrentConfig),
+    Transmission(transmission::TransmissionConfig),
+    Deluge(deluge::DelugeConfig),
+    RTorrent(rtorrent::RTorrentConfig),
+}
+
+impl ClientKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientKind::QBittorrent(_) => "qbittorrent",
+            ClientKind::Transmission(_) => "transmission",
+            ClientKind::Deluge(_) => "deluge",
+            ClientKind::RTorrent(_) => "rtorrent",
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RTorrentConfig {
+    /// Url of the xml-rpc endpoint, usually exposed by the web server in front of rTorrent or ruTorrent.
+    /// Ex: `http://localhost/RPC2`
+    pub url: Option<String>,
+    /// Path of the scgi socket of rTorrent, used instead of `url` when set. Ex: `/run/rtorrent/rpc.socket`
+    pub socket: Option<PathBuf>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TransmissionConfig {
+    /// Url of the rpc endpoint. Ex: `http://localhost:9091/transmission/rpc`
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
@@ -1,7 +1,8 @@
 use serde::{Deserialize,Serialize};
 use tracing::metadata::LevelFilter;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::env;
+use std::time::Duration;
 use std::collections::HashMap;
 use figment::{Figment, providers::{Format, Yaml, Env}};
 use figment::value::Value as FigmentValue;
@@ -17,24 +18,62 @@ fn default_bool_true() -> bool {
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
-    /// The path of the torrents to search.
-    torrents_path: String,
+    /// The path of the torrents to search. When this isn't set, the torrents are taken from the torrent clients,
+    /// exporting their `.torrent` files through the client's api when it can.
+    torrents_path: Option<String>,
     
     /// The output path of the torrents.
     output_path: Option<String>,
+
+    /// The path to store cross-seed's own data in, like the library index. Defaults to `data`.
+    data_path: Option<String>,
     
-    /// When running as script we exit the program after finishing. In daemon mode we run it at set intervals.
+    /// When running as script we exit the program after finishing. In daemon mode we run it at set intervals,
+    /// see the `daemon` section.
     #[serde(default)]
     pub run_mode: RunMode,
     
+    /// Config section for scheduling work when running as a daemon.
+    #[serde(default)]
+    pub daemon: super::DaemonConfig,
+
+    /// How often to poll the rss feeds of the indexers for new releases when running as a daemon.
+    /// Ex: `15m`, `1h`. Rss polling is disabled when this isn't set.
+    #[serde(default, with = "humantime_serde")]
+    pub rss_interval: Option<Duration>,
+    
     /// When running as inject we inject torrents cross-seed has found directly into the client, when running as search we populate the output folder.
     #[serde(default)]
     pub torrent_mode: TorrentMode,
 
-    /// Whether to cache using an external db (ie regis) or don't cache.
+    /// Run the whole search, but don't change anything in the torrent client or write any output files.
+    /// What would have been done is reported instead.
+    #[serde(default, alias = "dry-run")]
+    pub dry_run: bool,
+
+    /// Whether to store the search history in a local database, which is used to skip
+    /// torrents that were searched recently.
     #[serde(default)]
     pub use_cache: bool,
 
+    /// Don't search for a torrent on an indexer if it was searched for on it within this long.
+    /// Requires `use_cache`. Ex: `2d`.
+    #[serde(default, with = "humantime_serde")]
+    pub exclude_recent_search: Option<Duration>,
+
+    /// Don't search for a torrent on an indexer if it was first searched for on it more than
+    /// this long ago. Requires `use_cache`. Ex: `2w`.
+    #[serde(default, with = "humantime_serde")]
+    pub exclude_older: Option<Duration>,
+
+    /// Rules that decide which local torrents are searched for.
+    #[serde(default)]
+    pub filters: super::FilterConfig,
+
+    /// Config section for how cross-seeds are added, ex: adding them paused and rechecking them first.
+    #[serde(default)]
+    pub injection: super::InjectionConfig,
+
     /// Whether or not to strip public trackers from cross-seed torrents.
     #[serde(default)]
     pub strip_public_trackers: bool,
@@ -42,14 +81,51 @@ pub struct Config {
     #[serde(default)]
     pub log_level: LogLevel,
 
-    /// The category of added cross-seed torrents.
+    /// The category of added cross-seed torrents, unless `labels` chooses another one. Defaults to `cross-seed-rs`.
     torrent_category: Option<String>,
 
+    /// Config section for the category and tags of added cross-seeds, ex: keeping the category of the local torrent.
+    #[serde(default)]
+    pub labels: super::LabelConfig,
+
     /// The indexers to search.
+    #[serde(default)]
     pub indexers: Vec<Indexer>,
 
+    /// Config section for discovering indexers from prowlarr. The discovered indexers
+    /// are searched along with the ones in `indexers`.
+    pub prowlarr: Option<super::ProwlarrConfig>,
+
+    /// Proxy to route indexer requests and torrent downloads through.
+    /// Indexers can override this with their own proxy, and torrent clients only use it if they set `proxy`.
+    pub proxy: Option<super::ProxyConfig>,
+
+    /// Hosts that should never be proxied. Subdomains of the hosts are also matched.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+
+    /// Sinks to send notifications to, ex: when a cross-seed is added or a search finishes.
+    #[serde(default)]
+    pub notifications: Vec<super::NotificationConfig>,
+
+    /// Config section for the http server. The server only runs when running as a daemon.
+    pub server: Option<super::ServerConfig>,
+
+    /// The torrent clients to search and inject into. Each local torrent is cross-seeded in the client it's in.
+    #[serde(default)]
+    pub clients: Vec<super::client::ClientConfig>,
+
     /// Config section for qbittorrent client
     pub qbittorrent: Option<super::client::qbittorrent::QBittorrentConfig>,
+
+    /// Config section for transmission client
+    pub transmission: Option<super::client::transmission::TransmissionConfig>,
+
+    /// Config section for deluge client
+    pub deluge: Option<super::client::deluge::DelugeConfig>,
+
+    /// Config section for rtorrent client
+    pub rtorrent: Option<super::client::rtorrent::RTorrentConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -90,6 +166,16 @@ impl Default for TorrentMode {
     }
 }
 
+impl TorrentMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TorrentMode::InjectTrackers => "inject_trackers",
+            TorrentMode::InjectFile => "inject_file",
+            TorrentMode::Filesystem => "filesystem",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum LogLevel {
     #[serde(alias = "error")]
@@ -165,12 +251,12 @@ impl Config {
         config
     }
 
-    pub fn torrents_path(&self) -> &Path {
-        Path::new(&self.torrents_path)
+    pub fn torrents_path(&self) -> Option<&Path> {
+        self.torrents_path.as_ref().map(Path::new)
     }
 
-    pub fn torrents_path_str(&self) -> &str {
-        &self.torrents_path
+    pub fn torrents_path_str(&self) -> Option<&String> {
+        self.torrents_path.as_ref()
     }
 
     pub fn output_path(&self) -> Option<&Path> {
@@ -184,9 +270,108 @@ impl Config {
         self.output_path.as_ref()
     }
 
+    pub fn data_path(&self) -> &Path {
+        Path::new(self.data_path.as_deref().unwrap_or("data"))
+    }
+
+    /// The path of the persisted library index.
+    pub fn library_path(&self) -> PathBuf {
+        self.data_path().join("library.json")
+    }
+
+    /// The path to store the `.torrent` files exported from the torrent clients in. A dry run doesn't change
+    /// the data directory, so its exported torrents are kept in a temporary directory instead.
+    pub fn exported_torrents_path(&self) -> PathBuf {
+        match self.dry_run {
+            true => std::env::temp_dir().join("cross-seed-dry-run").join("torrents"),
+            false => self.data_path().join("torrents"),
+        }
+    }
+
+    /// The path of the local database.
+    pub fn database_path(&self) -> PathBuf {
+        self.data_path().join("cross-seed.db")
+    }
+
+    /// Every configured torrent client. The single client sections are included as clients named after their type,
+    /// unless a client in `clients` already has that name.
+    pub fn torrent_clients(&self) -> Vec<super::client::ClientConfig> {
+        use super::client::{ClientConfig, ClientKind};
+
+        let sections = [
+            self.qbittorrent.clone().map(ClientKind::QBittorrent),
+            self.transmission.clone().map(ClientKind::Transmission),
+            self.deluge.clone().map(ClientKind::Deluge),
+            self.rtorrent.clone().map(ClientKind::RTorrent),
+        ];
+
+        let mut clients = self.clients.clone();
+        for kind in sections.into_iter().flatten() {
+            let name = kind.as_str().to_string();
+            if !clients.iter().any(|client| client.name == name) {
+                clients.push(ClientConfig { name, kind, path_mappings: vec![], proxy: false });
+            }
+        }
+
+        clients
+    }
+
     pub fn torrent_category(&self) -> String {
         self.torrent_category.as_ref()
             .unwrap_or(&String::from("cross-seed-rs"))
             .clone()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::client::ClientKind;
+
+    fn config(yaml: &str) -> Config {
+        Figment::new().join(Yaml::string(yaml)).extract().unwrap()
+    }
+
+    #[test]
+    fn legacy_client_sections_merge_with_clients() {
+        let config = config(r#"
+clients:
+  - name: seedbox
+    type: transmission
+    url: http://seedbox:9091/transmission/rpc
+    proxy: true
+  - name: qbittorrent
+    type: qbittorrent
+    url: http://listed:8080
+    username: admin
+    password: listed
+qbittorrent:
+  url: http://legacy:8080
+  username: admin
+  password: legacy
+deluge:
+  url: http://deluge:8112
+  password: deluge
+"#);
+
+        let clients = config.torrent_clients();
+        let names: Vec<&str> = clients.iter().map(|client| client.name.as_str()).collect();
+        assert_eq!(names, vec!["seedbox", "qbittorrent", "deluge"]);
+
+        // A listed client with the name of a legacy section replaces the section.
+        assert!(matches!(&clients[1].kind, ClientKind::QBittorrent(qbittorrent) if qbittorrent.url == "http://listed:8080"));
+        assert!(matches!(&clients[2].kind, ClientKind::Deluge(deluge) if deluge.url == "http://deluge:8112"));
+        assert!(clients[0].proxy);
+        assert!(!clients[2].proxy);
+    }
+
+    #[test]
+    fn legacy_client_section_alone() {
+        let config = config("transmission:\n  url: http://localhost:9091/transmission/rpc\n");
+
+        let clients = config.torrent_clients();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].name, "transmission");
+        assert!(clients[0].path_mappings.is_empty());
+    }
+}
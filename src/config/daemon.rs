@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DaemonConfig {
+    /// How often to search the indexers for the whole library. Ex: `1d`, `12h`.
+    #[serde(default, with = "humantime_serde")]
+    pub search_interval: Option<Duration>,
+
+    /// Cron expression of when to search the indexers for the whole library, takes priority
+    /// over `search_interval`. The expression includes seconds. Ex: `0 0 4 * * *`
+    pub search_cron: Option<String>,
+
+    /// Time windows that scheduled searches and rss polls are allowed to run in, in local time.
+    /// Ex: `01:00-07:00`. When empty, they're allowed to run at any time.
+    #[serde(default)]
+    pub allowed_windows: Vec<TimeWindow>,
+
+    /// How often to refresh and save the library index. Ex: `1h`.
+    #[serde(default, with = "humantime_serde")]
+    pub housekeeping_interval: Option<Duration>,
+
+    /// How often to poll the client for torrents that finished downloading, which are then
+    /// searched for right away. Ex: `1m`. Disabled when this isn't set.
+    #[serde(default, with = "humantime_serde")]
+    pub completion_interval: Option<Duration>,
+}
+
+impl DaemonConfig {
+    /// Check if scheduled work is allowed to run at a time.
+    pub fn is_allowed_at(&self, time: NaiveTime) -> bool {
+        self.allowed_windows.is_empty() || self.allowed_windows.iter().any(|window| window.contains(time))
+    }
+}
+
+/// A window of time in a day. The window can wrap around midnight, ex: `22:00-04:00`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (start, end) = s.split_once('-')
+            .ok_or_else(|| format!("Invalid time window, expected `HH:MM-HH:MM`: {}", s))?;
+
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|e| format!("Invalid time in window {}: {}", s, e));
+
+        Ok(TimeWindow {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        format!("{}-{}", window.start.format("%H:%M"), window.end.format("%H:%M"))
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Rules that decide which local torrents are searched for. A torrent is only searched
+/// if it passes every rule that is set.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FilterConfig {
+    /// Only search torrents in these client categories. When empty, all categories are searched.
+    #[serde(default)]
+    pub include_categories: Vec<String>,
+
+    /// Never search torrents in these client categories.
+    #[serde(default)]
+    pub exclude_categories: Vec<String>,
+
+    /// Only search torrents that have at least one of these client tags. When empty, all torrents are searched.
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+
+    /// Never search torrents that have any of these client tags.
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+
+    /// Only search torrents that announce to at least one of these tracker hosts. Subdomains of
+    /// the hosts are also matched. Ex: `tracker.example.org`
+    #[serde(default)]
+    pub include_trackers: Vec<String>,
+
+    /// Never search torrents that announce to any of these tracker hosts.
+    #[serde(default)]
+    pub exclude_trackers: Vec<String>,
+
+    /// Only search torrents with a name matching this regex.
+    pub include_name: Option<String>,
+
+    /// Never search torrents with a name matching this regex. Ex: `(?i)\bsample\b`
+    pub exclude_name: Option<String>,
+
+    /// The minimum total size of a torrent in bytes.
+    pub min_size: Option<u64>,
+
+    /// The maximum total size of a torrent in bytes.
+    pub max_size: Option<u64>,
+
+    /// The minimum amount of files in a torrent.
+    pub min_files: Option<usize>,
+
+    /// The maximum amount of files in a torrent.
+    pub max_files: Option<usize>,
+
+    /// Only search torrents that were added at least this long ago. Ex: `1h`.
+    #[serde(default, with = "humantime_serde")]
+    pub min_age: Option<Duration>,
+
+    /// Only search torrents that were added at most this long ago. Ex: `52w`.
+    #[serde(default, with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+
+    /// Only search single episodes or season packs, based on the name of the torrent.
+    #[serde(default)]
+    pub content: ContentFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ContentFilter {
+    #[serde(alias = "all")]
+    All,
+
+    /// Only search torrents of a single episode. Ex: `Show.S01E01.1080p`
+    #[serde(alias = "episodes")]
+    Episodes,
+
+    /// Only search torrents of whole seasons. Ex: `Show.S01.1080p`
+    #[serde(alias = "season_packs", alias = "seasonpacks")]
+    SeasonPacks,
+}
+
+impl Default for ContentFilter {
+    fn default() -> Self {
+        ContentFilter::All
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How long to wait between polls of a rechecking torrent when `poll_interval` isn't set.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a recheck may take when `check_timeout` isn't set.
+const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// The tag added to incomplete torrents when `review_tag` isn't set.
+const DEFAULT_REVIEW_TAG: &str = "cross-seed-review";
+
+/// Config section for how cross-seeds are added to the client.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InjectionConfig {
+    /// Add cross-seeds paused and recheck them, only resuming them when they're complete. Enabled by default,
+    /// so a bad match can't start downloading over the existing data.
+    pub verify: bool,
+
+    /// What to do with a cross-seed that isn't complete after its recheck.
+    pub on_incomplete: IncompletePolicy,
+
+    /// The tag to add to incomplete cross-seeds with the `tag` policy. Defaults to `cross-seed-review`.
+    pub review_tag: Option<String>,
+
+    /// How often to poll the client while a cross-seed is rechecking. Ex: `5s`.
+    #[serde(with = "humantime_serde")]
+    pub poll_interval: Option<Duration>,
+
+    /// How long to wait for a recheck to finish before leaving the cross-seed paused. Ex: `30m`.
+    #[serde(with = "humantime_serde")]
+    pub check_timeout: Option<Duration>,
+}
+
+impl Default for InjectionConfig {
+    fn default() -> Self {
+        Self {
+            verify: true,
+            on_incomplete: IncompletePolicy::default(),
+            review_tag: None,
+            poll_interval: None,
+            check_timeout: None,
+        }
+    }
+}
+
+impl InjectionConfig {
+    pub fn review_tag(&self) -> String {
+        self.review_tag.clone().unwrap_or_else(|| String::from(DEFAULT_REVIEW_TAG))
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL)
+    }
+
+    pub fn check_timeout(&self) -> Duration {
+        self.check_timeout.unwrap_or(DEFAULT_CHECK_TIMEOUT)
+    }
+}
+
+/// What to do with a cross-seed that isn't complete after its recheck.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncompletePolicy {
+    /// Leave the cross-seed paused in the client.
+    #[default]
+    Pause,
+    /// Leave the cross-seed paused, and tag it for review.
+    Tag,
+    /// Remove the cross-seed from the client, without deleting any files.
+    Remove,
+}
+
+impl IncompletePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IncompletePolicy::Pause => "left paused",
+            IncompletePolicy::Tag => "tagged for review",
+            IncompletePolicy::Remove => "removed",
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The suffix added to the source category in `suffix` mode when `category_suffix` isn't set.
+const DEFAULT_CATEGORY_SUFFIX: &str = ".cross-seed";
+
+/// Config section for the category and tags of added cross-seeds. Deluge and rTorrent don't have tags, so
+/// their cross-seeds only get a category.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LabelConfig {
+    /// How the category of a cross-seed is chosen.
+    pub category: CategoryMode,
+
+    /// Added to the category of the local torrent in `suffix` mode. Defaults to `.cross-seed`.
+    pub category_suffix: Option<String>,
+
+    /// Categories for cross-seeds from these indexers, by indexer name. They're used instead of the category mode.
+    pub indexer_categories: HashMap<String, String>,
+
+    /// Copy the tags of the local torrent to the cross-seed.
+    pub copy_tags: bool,
+
+    /// Tags added to every cross-seed. Tags can use `{indexer}`, `{client}` and `{category}`, the category
+    /// of the local torrent. Ex: `["cross-seed", "{indexer}"]`
+    pub tags: Vec<String>,
+}
+
+/// How the category of a cross-seed is chosen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CategoryMode {
+    /// Always use `torrent_category`.
+    #[default]
+    Fixed,
+    /// Use the category of the local torrent.
+    Source,
+    /// Use the category of the local torrent with `category_suffix` added, ex: `movies.cross-seed`.
+    Suffix,
+}
+
+impl LabelConfig {
+    /// The category of a cross-seed from `indexer` of a local torrent in `source_category`. `fixed` is used
+    /// in `fixed` mode, and when the local torrent doesn't have a category.
+    pub fn category(&self, indexer: &str, source_category: &str, fixed: String) -> String {
+        if let Some(category) = self.indexer_categories.get(indexer) {
+            return category.clone();
+        }
+
+        match self.category {
+            _ if source_category.is_empty() => fixed,
+            CategoryMode::Fixed => fixed,
+            CategoryMode::Source => source_category.to_string(),
+            CategoryMode::Suffix => {
+                let suffix = self.category_suffix.as_deref().unwrap_or(DEFAULT_CATEGORY_SUFFIX);
+                format!("{}{}", source_category, suffix)
+            },
+        }
+    }
+
+    /// The tags of a cross-seed from `indexer` in `client` of a local torrent with `source_tags` in `source_category`.
+    pub fn tags(&self, indexer: &str, client: &str, source_category: &str, source_tags: &[String]) -> Vec<String> {
+        let mut tags: Vec<String> = match self.copy_tags {
+            true => source_tags.to_vec(),
+            false => vec![],
+        };
+
+        let rendered = self.tags.iter()
+            .map(|tag| tag.replace("{indexer}", indexer)
+                .replace("{client}", client)
+                .replace("{category}", source_category))
+            .filter(|tag| !tag.is_empty());
+
+        for tag in rendered {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(category: CategoryMode) -> LabelConfig {
+        LabelConfig { category, ..LabelConfig::default() }
+    }
+
+    #[test]
+    fn category_modes() {
+        let fixed = || String::from("cross-seed");
+
+        assert_eq!(config(CategoryMode::Fixed).category("Tracker", "movies", fixed()), "cross-seed");
+        assert_eq!(config(CategoryMode::Source).category("Tracker", "movies", fixed()), "movies");
+        assert_eq!(config(CategoryMode::Suffix).category("Tracker", "movies", fixed()), "movies.cross-seed");
+
+        let custom_suffix = LabelConfig { category_suffix: Some(String::from("-xs")), ..config(CategoryMode::Suffix) };
+        assert_eq!(custom_suffix.category("Tracker", "movies", fixed()), "movies-xs");
+    }
+
+    #[test]
+    fn category_without_source_category() {
+        assert_eq!(config(CategoryMode::Source).category("Tracker", "", String::from("cross-seed")), "cross-seed");
+        assert_eq!(config(CategoryMode::Suffix).category("Tracker", "", String::from("cross-seed")), "cross-seed");
+    }
+
+    #[test]
+    fn category_of_indexer() {
+        let mut config = config(CategoryMode::Suffix);
+        config.indexer_categories.insert(String::from("Tracker"), String::from("tracker-movies"));
+
+        assert_eq!(config.category("Tracker", "movies", String::from("cross-seed")), "tracker-movies");
+        assert_eq!(config.category("Other", "movies", String::from("cross-seed")), "movies.cross-seed");
+    }
+
+    #[test]
+    fn render_tags() {
+        let config = LabelConfig {
+            tags: vec![String::from("cross-seed"), String::from("{indexer}"), String::from("{client}-{category}")],
+            ..LabelConfig::default()
+        };
+
+        assert_eq!(config.tags("Tracker", "qbit", "movies", &[String::from("hd")]),
+            vec!["cross-seed", "Tracker", "qbit-movies"]);
+    }
+
+    #[test]
+    fn tags_skip_empty_and_duplicates() {
+        let config = LabelConfig {
+            copy_tags: true,
+            tags: vec![String::from("{category}"), String::from("hd"), String::from("{indexer}")],
+            ..LabelConfig::default()
+        };
+
+        assert_eq!(config.tags("hd", "qbit", "", &[String::from("hd"), String::from("4k")]),
+            vec!["hd", "4k"]);
+    }
+}
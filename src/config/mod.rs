@@ -7,4 +7,29 @@ pub use argument_tree::*;
 pub mod cli_provider;
 pub use cli_provider::*;
 
-pub mod client;
\ No newline at end of file
+pub mod client;
+
+pub mod proxy;
+pub use proxy::*;
+
+
+pub mod prowlarr;
+pub use prowlarr::*;
+
+pub mod server;
+pub use server::*;
+
+pub mod daemon;
+pub use daemon::*;
+
+pub mod filters;
+pub use filters::*;
+
+pub mod notifications;
+pub use notifications::*;
+
+pub mod injection;
+pub use injection::*;
+
+pub mod labels;
+pub use labels::*;
\ No newline at end of file
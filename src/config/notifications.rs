@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A sink that notifications are sent to.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NotificationConfig {
+    /// The kind of sink.
+    #[serde(rename = "type")]
+    pub kind: NotificationKind,
+
+    /// Url to send the notifications to, required for `webhook` and `discord` sinks.
+    pub url: Option<String>,
+
+    /// Shell command to run for every notification, required for `command` sinks. The fields
+    /// of the notification are passed as `CROSS_SEED_*` environment variables.
+    /// Ex: `notify-send "$CROSS_SEED_MESSAGE"`
+    pub command: Option<String>,
+
+    /// The events to send notifications for. When empty, notifications are sent for every event.
+    #[serde(default)]
+    pub events: Vec<NotificationEvent>,
+
+    /// Message templates for events, replacing the default message. Templates can use `{name}`,
+    /// `{indexer}`, `{size}` and `{mode}`, and for run summaries `{searched}`, `{injected}`,
+    /// `{failed}` and `{duration}`. Ex: `injected: "Added {name} from {indexer}"`
+    #[serde(default)]
+    pub templates: HashMap<NotificationEvent, String>,
+}
+
+impl NotificationConfig {
+    /// Check if this sink wants notifications for an event.
+    pub fn wants(&self, event: NotificationEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum NotificationKind {
+    /// POST a json object with the message and fields of the notification.
+    #[serde(alias = "webhook")]
+    Webhook,
+
+    /// POST a discord compatible webhook payload.
+    #[serde(alias = "discord")]
+    Discord,
+
+    /// Run a shell command.
+    #[serde(alias = "command")]
+    Command,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum NotificationEvent {
+    /// A cross-seed was injected into the client.
+    #[serde(alias = "injected")]
+    Injected,
+
+    /// A cross-seed was saved to the output path.
+    #[serde(alias = "saved")]
+    Saved,
+
+    /// A full library search finished.
+    #[serde(alias = "run_finished", alias = "runfinished")]
+    RunFinished,
+
+    /// Requests to an indexer started failing.
+    #[serde(alias = "indexer_failing", alias = "indexerfailing")]
+    IndexerFailing,
+
+    /// Requests to the torrent client started failing.
+    #[serde(alias = "client_failing", alias = "clientfailing")]
+    ClientFailing,
+}
+
+impl NotificationEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::Injected => "injected",
+            NotificationEvent::Saved => "saved",
+            NotificationEvent::RunFinished => "run_finished",
+            NotificationEvent::IndexerFailing => "indexer_failing",
+            NotificationEvent::ClientFailing => "client_failing",
+        }
+    }
+
+    /// The message template used when a sink doesn't have its own.
+    pub fn default_template(&self) -> &'static str {
+        match self {
+            NotificationEvent::Injected => "Injected cross-seed {name} from {indexer} ({size}, {mode})",
+            NotificationEvent::Saved => "Saved cross-seed {name} from {indexer} ({size})",
+            NotificationEvent::RunFinished => "Searched {searched} torrents in {duration}, added {injected} cross-seeds ({failed} searches failed)",
+            NotificationEvent::IndexerFailing => "Indexer {indexer} is failing: {error}",
+            NotificationEvent::ClientFailing => "Torrent client {client} is failing: {error}",
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProwlarrConfig {
+    /// Base url of prowlarr. Ex: `http://localhost:9696`
+    pub url: String,
+
+    /// API key of prowlarr.
+    pub api_key: String,
+
+    /// Only use indexers with these names. When empty, all indexers are used.
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Never use indexers with these names.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Only use indexers that have at least one of these tags. When empty, all indexers are used.
+    #[serde(default)]
+    pub include_tags: Vec<String>,
+
+    /// Never use indexers that have any of these tags.
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// A proxy that http requests can be routed through.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProxyConfig {
+    /// Url of the proxy, supports http, https and socks5 proxies.
+    /// Ex: `socks5://127.0.0.1:1080`
+    pub url: String,
+
+    /// Username to authenticate with the proxy.
+    pub username: Option<String>,
+
+    /// Password to authenticate with the proxy.
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Get the url of the proxy with the credentials inserted into it.
+    pub fn proxy_url(&self) -> Result<reqwest::Url, ProxyError> {
+        let mut url = reqwest::Url::parse(&self.url)
+            .map_err(|_| ProxyError::InvalidUrl(self.url.clone()))?;
+
+        match url.scheme() {
+            "http" | "https" | "socks5" | "socks5h" => {},
+            scheme => return Err(ProxyError::UnsupportedScheme(scheme.to_string())),
+        }
+
+        if let Some(username) = &self.username {
+            url.set_username(username)
+                .map_err(|_| ProxyError::InvalidUrl(self.url.clone()))?;
+            url.set_password(self.password.as_deref())
+                .map_err(|_| ProxyError::InvalidUrl(self.url.clone()))?;
+        }
+
+        Ok(url)
+    }
+
+    /// Create a reqwest proxy that will not be used for the hosts in `no_proxy`.
+    pub fn to_reqwest_proxy(&self, no_proxy: &[String]) -> Result<reqwest::Proxy, ProxyError> {
+        let proxy_url = self.proxy_url()?;
+        let no_proxy = no_proxy.to_vec();
+
+        Ok(reqwest::Proxy::custom(move |url| {
+            match url.host_str() {
+                Some(host) if is_no_proxy_host(host, &no_proxy) => None,
+                _ => Some(proxy_url.clone()),
+            }
+        }))
+    }
+}
+
+/// Check if a host matches an entry in the `no_proxy` list.
+///
+/// Entries match the host exactly, or any subdomain of it. An entry of `*`
+/// matches every host.
+pub fn is_no_proxy_host(host: &str, no_proxy: &[String]) -> bool {
+    let host = host.to_lowercase();
+
+    no_proxy.iter().any(|entry| {
+        let entry = entry.trim().trim_start_matches('.').to_lowercase();
+
+        entry == "*" || host == entry || host.ends_with(&format!(".{}", entry))
+    })
+}
+
+#[derive(Debug)]
+pub enum ProxyError {
+    InvalidUrl(String),
+    UnsupportedScheme(String),
+    HttpError(reqwest::Error),
+}
+
+impl From<reqwest::Error> for ProxyError {
+    fn from(e: reqwest::Error) -> Self {
+        ProxyError::HttpError(e)
+    }
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+fn default_bind_address() -> String {
+    String::from("127.0.0.1:2468")
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerConfig {
+    /// The address the http server listens on. Defaults to `127.0.0.1:2468`.
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+
+    /// API key that requests must send in the `X-Api-Key` header, or in the `apikey`
+    /// query parameter. It's required, the server can download urls and add torrents.
+    pub api_key: String,
+}
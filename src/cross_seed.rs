@@ -1,54 +1,155 @@
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use lava_torrent::torrent::v1::Torrent;
-use lava_torrent::bencode::BencodeElem;
+use tokio::sync::{watch, RwLock, RwLockWriteGuard};
 use tracing::{debug, error, info};
 
-use crate::{config::{Config, TorrentMode}, indexer::Indexer};
+use crate::{config::{Config, NotificationEvent, TorrentMode}, indexer::Indexer};
+use crate::database::{Database, DatabaseError, Decision, DecisionRecord, SearchOutcome};
+use crate::dry_run::{DryRunReport, PlannedAction};
+use crate::health::HealthTracker;
+use crate::injection::{self, InjectionOutcome};
+use crate::library::{self, LibraryEntry, LibraryIndex, TorrentMetadata};
+use crate::notifications::{Notification, Notifier};
+use crate::torznab::TorrentResult;
 
-use abstracttorrent::torrent::{TorrentUpload, TorrentState, TorrentInfo};
+use crate::torrent_client::{ClientError, ContentLayout, TorrentClient, TorrentClients, TorrentUpload, TorrentUploadBuilder, TorrentState, TorrentInfo};
 
 pub struct CrossSeed {
     config: Arc<Config>,
     indexers: Arc<Vec<Indexer>>,
-    torrent_client: Arc<crate::torrent_client::TorrentClient>,
+    torrent_clients: Arc<TorrentClients>,
+    library: RwLock<LibraryIndex>,
+    database: Option<Database>,
+    health: HealthTracker,
+    notifier: Notifier,
+    dry_run: DryRunReport,
+    /// Read locked while a cross-seed is being added, so the shutdown can wait for it.
+    injections: RwLock<()>,
 }
 
 #[allow(dead_code)]
 impl CrossSeed {
-    pub fn new(config: Config, indexers: Vec<Indexer>, torrent_client: crate::torrent_client::TorrentClient) -> Self {
+    pub fn new(config: Config, indexers: Vec<Indexer>, torrent_clients: TorrentClients, library: LibraryIndex, database: Option<Database>) -> Self {
         Self {
+            notifier: Notifier::new(config.notifications.clone()),
             config: Arc::new(config),
             indexers: Arc::new(indexers),
-            torrent_client: Arc::new(torrent_client),
+            torrent_clients: Arc::new(torrent_clients),
+            library: RwLock::new(library),
+            database,
+            health: HealthTracker::default(),
+            dry_run: DryRunReport::default(),
+            injections: RwLock::new(()),
         }
     }
 
-    pub fn new_arcs(config: Arc<Config>, indexers: Arc<Vec<Indexer>>, torrent_client: Arc<crate::torrent_client::TorrentClient>) -> Self {
+    pub fn new_arcs(config: Arc<Config>, indexers: Arc<Vec<Indexer>>, torrent_clients: Arc<TorrentClients>, library: LibraryIndex, database: Option<Database>) -> Self {
         Self {
+            notifier: Notifier::new(config.notifications.clone()),
             config,
             indexers,
-            torrent_client,
+            torrent_clients,
+            library: RwLock::new(library),
+            database,
+            health: HealthTracker::default(),
+            dry_run: DryRunReport::default(),
+            injections: RwLock::new(()),
+        }
+    }
+
+    pub fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    pub fn torrent_clients(&self) -> &Arc<TorrentClients> {
+        &self.torrent_clients
+    }
+
+    pub fn indexers(&self) -> &Arc<Vec<Indexer>> {
+        &self.indexers
+    }
+
+    pub fn library(&self) -> &RwLock<LibraryIndex> {
+        &self.library
+    }
+
+    pub fn database(&self) -> Option<&Database> {
+        self.database.as_ref()
+    }
+
+    pub fn health(&self) -> &HealthTracker {
+        &self.health
+    }
+
+    pub fn notifier(&self) -> &Notifier {
+        &self.notifier
+    }
+
+    /// The actions that were skipped because of `dry_run`.
+    pub fn dry_run_report(&self) -> &DryRunReport {
+        &self.dry_run
+    }
+
+    /// Wait for the cross-seeds that are being added, and stop new ones from being added while the returned
+    /// guard is held. Used on shutdown, so a local torrent is never left removed during its re-add.
+    pub async fn stop_injections(&self) -> RwLockWriteGuard<'_, ()> {
+        self.injections.write().await
+    }
+
+    /// Record a failed request to an indexer, and notify if it just started failing.
+    pub async fn indexer_failed(&self, indexer_name: &str, error: String) {
+        if self.health.record_failure(indexer_name, error.clone()) {
+            self.notifier.notify(Notification::new(NotificationEvent::IndexerFailing)
+                .field("indexer", indexer_name)
+                .field("error", error)).await;
+        }
+    }
+
+    /// Record a failed request to a torrent client, and notify if it just started failing.
+    pub async fn client_failed(&self, client: &TorrentClient, error: String) {
+        crate::metrics::METRICS.client_errors.inc();
+
+        if self.health.record_client_failure(client.name(), error.clone()) {
+            self.notifier.notify(Notification::new(NotificationEvent::ClientFailing)
+                .field("client", client.name())
+                .field("error", error)).await;
         }
     }
 
     /// Start searching for all torrents, this searches for torrents in sequential order.
-    pub async fn start_searching(&self, torrents: Vec<Torrent>) -> Result<(), CrossSeedError> {
+    pub async fn start_searching(&self, torrents: Vec<TorrentMetadata>, shutdown: &watch::Receiver<bool>) -> Result<(), CrossSeedError> {
         for torrent in torrents.iter() {
-            self.search_for_torrent(torrent).await?;
+            self.search_for_torrent(torrent, shutdown).await?;
         }
 
         Ok(())
     }
 
-    /// Search for a specific torrent in the indexers.
-    pub async fn search_for_torrent(&self, torrent: &Torrent) -> Result<(), CrossSeedError> {
+    /// Search for a specific torrent in the indexers. Returns the amount of cross-seeds that were added.
+    /// Once `shutdown` is set, the indexers that weren't searched yet are skipped.
+    pub async fn search_for_torrent(&self, torrent: &TorrentMetadata, shutdown: &watch::Receiver<bool>) -> Result<usize, CrossSeedError> {
         // TODO: Add a `tracing` log scope.
+        let hash = &torrent.info_hash;
+        let mut injected = 0;
 
-        for indexer in self.indexers.iter() {
-            match self.torrent_client.get_torrent_info(&torrent).await? {
-                Some(info) => match self.search_for_cross_torrent(indexer, torrent, info.clone()).await? {
-                    Some(found_torrent) => self.add_cross_seed_torrent(&torrent, found_torrent, info).await?,
+        for indexer in self.indexers.iter().filter(|indexer| indexer.enabled) {
+            if *shutdown.borrow() {
+                debug!("Stopping search for {} because of the shutdown", torrent.name);
+                break;
+            }
+
+            if !self.should_search(hash, indexer) {
+                debug!("Skipping search for {} on {}, it was searched before", torrent.name, indexer.name);
+                continue;
+            }
+
+            match self.torrent_clients.find_torrent(hash).await? {
+                Some((client, info)) => match self.search_and_record(indexer, torrent, client, info.clone()).await? {
+                    Some(found_torrent) => if self.add_cross_seed_torrent(&indexer.name, torrent, found_torrent, client, info).await? == Decision::Injected {
+                        injected += 1;
+                    },
                     /* {
                         match self.torrent_client.get_torrent_info(&torrent).await? {
                             Some(info) => self.add_cross_seed_torrent(&torrent, found_torrent, info).await?,
@@ -57,170 +158,528 @@ impl CrossSeed {
                     }, */
                     None => {}, // TODO
                 },
-                None => error!("Failed to find torrent in the client!"), // TODO
+                None => error!("Failed to find torrent in any client!"), // TODO
             }
             
         }
 
-        Ok(())
+        Ok(injected)
+    }
+
+    /// Check the search history to see if a torrent should be searched for on an indexer.
+    /// The torrent is searched for if the history can't be read, an extra search is cheaper than a missed one.
+    fn should_search(&self, info_hash: &str, indexer: &Indexer) -> bool {
+        let db = match &self.database {
+            Some(db) if self.config.use_cache => db,
+            _ => return true,
+        };
+
+        match db.should_search(info_hash, &indexer.name, self.config.exclude_recent_search, self.config.exclude_older) {
+            Ok(should_search) => should_search,
+            Err(err) => {
+                error!("Failed to read the search history of {} on {}, searching anyway: {:?}", info_hash, indexer.name, err);
+                true
+            },
+        }
+    }
+
+    /// Search for a cross-seed of a torrent on an indexer, and record the search in the history.
+    async fn search_and_record(&self, indexer: &Indexer, torrent: &TorrentMetadata, client: &TorrentClient, info: TorrentInfo) -> Result<Option<Torrent>, CrossSeedError> {
+        let result = self.search_for_cross_torrent(indexer, torrent, client, info).await;
+
+        match &result {
+            Ok(_) => self.health.record_success(&indexer.name),
+            Err(CrossSeedError::TorznabClient(err)) => self.indexer_failed(&indexer.name, format!("{:?}", err)).await,
+            Err(CrossSeedError::TorrentClient(err)) => self.client_failed(client, format!("{:?}", err)).await,
+            Err(_) => {},
+        }
+
+        // A dry run doesn't add anything, so its searches must not stop the torrent from being searched again.
+        if let Some(db) = self.database.as_ref().filter(|_| self.config.use_cache && !self.config.dry_run) {
+            let outcome = match &result {
+                Ok(Some(_)) => SearchOutcome::Found,
+                Ok(None) => SearchOutcome::NotFound,
+                Err(_) => SearchOutcome::Error,
+            };
+
+            db.record_search(&torrent.info_hash, &indexer.name, outcome)?;
+        }
+
+        result
     }
 
-    pub async fn add_cross_seed_torrent(&self, torrent: &Torrent, found_torrent: Torrent, info: TorrentInfo) -> Result<(), CrossSeedError> {
+    /// Add a cross-seed of a local torrent found on an indexer to the client holding the local torrent, and record
+    /// the decision in the decision log. Returns the decision that was made.
+    pub async fn add_cross_seed_torrent(&self, indexer_name: &str, torrent: &TorrentMetadata, found_torrent: Torrent, client: &TorrentClient, info: TorrentInfo) -> Result<Decision, CrossSeedError> {
+        let mut record = DecisionRecord::new(&torrent.info_hash, &torrent.name, indexer_name, &found_torrent, Decision::Injected);
+
+        let metrics = &crate::metrics::METRICS;
+        let mode = self.config.torrent_mode.as_str();
+        metrics.torrents_matched.with_label_values(&[mode]).inc();
+
         match info.state {
+            TorrentState::Uploading | TorrentState::QueuedUploading if self.config.dry_run => {
+                let action = match self.plan_cross_seed_torrent(indexer_name, torrent, &found_torrent, client, &info).await {
+                    Ok(action) => action,
+                    Err(err) => return Err(self.record_error(record, err)),
+                };
+
+                self.dry_run.record(PlannedAction {
+                    name: torrent.name.clone(),
+                    indexer: indexer_name.to_string(),
+                    candidate_name: found_torrent.name.clone(),
+                    action,
+                });
+                record.decision = Decision::DryRun;
+            },
             TorrentState::Uploading | TorrentState::QueuedUploading => {
-                match self.config.torrent_mode {
-                    TorrentMode::InjectTrackers => {
-                        if found_torrent.is_private() {
-                            debug!("The found torrent is private, so we must remove the torrent and re-add it with the new trackers...");
-
-                            // We have to merge the announce urls before we remove the torrent since we retrieve the
-                            // urls from the torrent client.
-                            let torrent = self.merge_torrent_announces(&torrent, &found_torrent).await?;
-
-                            self.torrent_client.remove_torrent(&info, false).await?;
-                            
-                            debug!("Re-uploading torrent to client...");
-
-                            // Clone some fields from the torrent due to ownership issues with
-                            // torrent.encode()
-                            let name = torrent.name.clone();
-                            let hash = torrent.info_hash().clone();
-
-                            match torrent.encode() {
-                                Ok(bytes) => {
-                                    let upload = TorrentUpload::builder()
-                                        .category(self.config.torrent_category())
-                                        .tags(info.tags)
-                                        .torrent_data(format!("{}.torrent", hash), bytes)
-                                        //.paused()
-                                        .build();
-
-                                    match self.torrent_client.add_torrent(&upload).await {
-                                        Ok(()) => info!("Added cross-seed torrent {}!", name),
-                                        Err(err) => error!("Error adding cross-seed torrent: {} (error: {:?}", name, err),
-                                    }
-                                },
-                                Err(e) => error!("Error encoding torrent for upload: {}", e),
-                            }
-                        } else {
-                            debug!("Adding trackers to torrent since they aren't private...");
-                            // Flatten the announce list
-                            let found_announces: Vec<String> = found_torrent.announce_list.as_ref()
-                                .unwrap().iter()
-                                .flat_map(|array| array.iter())
-                                .into_iter().cloned()
-                                .collect();
-
-                            if let Err(err) = self.torrent_client.add_torrent_trackers(&info, found_announces).await {
-                                error!("Error adding torrent trackers to torrent: {} (err: {:?})", torrent.name, err);
-                            }       
-                        }
+                let name = found_torrent.name.clone();
+                let size = found_torrent.length as u64;
+
+                match self.inject_cross_seed_torrent(indexer_name, torrent, found_torrent, client, info).await {
+                    Ok(outcome) if !outcome.is_seeding() => {
+                        // The client answered, it's the cross-seed that isn't usable.
+                        self.health.record_client_success(client.name());
+
+                        record.decision = Decision::Incomplete;
+                        record.detail = outcome.detail();
                     },
-                    TorrentMode::InjectFile => {
-                        debug!("Cannot add trackers, uploading new torrent...");
-
-                        // Clone some fields from the torrent due to ownership issues with
-                        // found_torrent.encode()
-                        let name = found_torrent.name.clone();
-                        let hash = found_torrent.info_hash().clone();
-
-                        match found_torrent.encode() {
-                            Ok(bytes) => {
-                                let upload = TorrentUpload::builder()
-                                    .torrent_data(format!("{}.torrent", hash), bytes)
-                                    .category(self.config.torrent_category())
-                                    //.paused() // TODO: don't pause new uploads
-                                    .build();
-
-                                match self.torrent_client.add_torrent(&upload).await {
-                                    Ok(()) => info!("Added cross-seed torrent {}!", name),
-                                    Err(err) => error!("Failure to add cross-seed torrent: {} (Error {:?})", name, err),
-                                }
-                            }, 
-                            Err(e) => error!("Failure to encode ({}) {}", e, name),
+                    Ok(_) => {
+                        metrics.torrents_injected.with_label_values(&[mode]).inc();
+                        self.health.record_client_success(client.name());
+
+                        let event = match self.config.torrent_mode {
+                            TorrentMode::Filesystem => NotificationEvent::Saved,
+                            _ => NotificationEvent::Injected,
+                        };
+                        self.notifier.notify(Notification::new(event)
+                            .field("name", &name)
+                            .field("indexer", indexer_name)
+                            .field("size", crate::util::format_size(size))
+                            .field("mode", mode)
+                            .field("client", client.name())).await;
+                    },
+                    Err(err) => {
+                        error!("Failed to add cross-seed torrent {}: {:?}", name, err);
+
+                        if let CrossSeedError::TorrentClient(client_err) = &err {
+                            self.client_failed(client, format!("{:?}", client_err)).await;
                         }
+                        record.decision = Decision::Error;
+                        record.detail = Some(format!("{:?}", err));
                     },
-                    TorrentMode::Filesystem => {
-                        todo!(); // TODO: implement
-                    }
                 }
             },
-            _ => debug!("Torrent is not done downloading, skipping..."),
+            _ => {
+                debug!("Torrent is not done downloading, skipping...");
+                record.decision = Decision::NotComplete;
+            },
         }
 
-        Ok(())
+        let decision = record.decision;
+        self.record_decision(record);
+
+        Ok(decision)
     }
 
-    /// Merge two torrent's announce urls into one torrent.
-    pub async fn merge_torrent_announces(&self, torrent: &Torrent, found_torrent: &Torrent) -> Result<Torrent, abstracttorrent::error::ClientError> {
-        // Get announce urls of both torrents.
-        let request_info = TorrentInfo::from_hash(torrent.info_hash());
-        let torrent_announces = self.torrent_client.get_torrent_trackers(&request_info).await?;
-        let torrent_announces: Vec<&String> = torrent_announces.iter().map(|t| &t.url).collect();
-
-        // Flatten the announce list
-        let found_announces: Vec<&String> = found_torrent.announce_list.as_ref()
-            .unwrap().iter()
-            .flat_map(|array| array.iter())
-            .collect();
+    /// Describe what `inject_cross_seed_torrent` would do, without changing anything in the client.
+    async fn plan_cross_seed_torrent(&self, indexer_name: &str, torrent: &TorrentMetadata, found_torrent: &Torrent, client: &TorrentClient, info: &TorrentInfo) -> Result<String, CrossSeedError> {
+        let action = match self.config.torrent_mode {
+            TorrentMode::InjectTrackers if found_torrent.is_private() => {
+                let merged = self.merge_torrent_announces(client, torrent, found_torrent).await?;
+                let trackers = announce_urls(&merged).len();
+
+                format!("remove {} from {} and re-add it with {} trackers in its category {}, restoring it if that fails",
+                    torrent.name, client.name(), trackers, info.category)
+            },
+            TorrentMode::InjectTrackers => {
+                let trackers = announce_urls(found_torrent).len();
+
+                format!("add {} trackers of {} to {} in {}", trackers, found_torrent.name, torrent.name, client.name())
+            },
+            TorrentMode::InjectFile => {
+                let (category, tags) = self.cross_seed_labels(indexer_name, client, info);
+
+                format!("add {} to {} in category {} with tags {:?}{}",
+                    found_torrent.name, client.name(), category, tags, self.plan_verification())
+            },
+            TorrentMode::Filesystem => {
+                let output = self.config.output_path_str().map_or("the output path", |path| path.as_str());
+
+                format!("save {}.torrent to {}", found_torrent.info_hash(), output)
+            },
+        };
+
+        Ok(action)
+    }
+
+    /// The category and tags of a cross-seed from `indexer_name` of the local torrent `info`.
+    fn cross_seed_labels(&self, indexer_name: &str, client: &TorrentClient, info: &TorrentInfo) -> (String, Vec<String>) {
+        let labels = &self.config.labels;
+
+        let category = labels.category(indexer_name, &info.category, self.config.torrent_category());
+        let tags = labels.tags(indexer_name, client.name(), &info.category, &info.tags);
+
+        (category, tags)
+    }
+
+    /// Describe the recheck of an added torrent for the dry run plan.
+    fn plan_verification(&self) -> String {
+        let injection = &self.config.injection;
+
+        match injection.verify {
+            true => format!(", paused until a recheck finds it complete ({} if it isn't)", injection.on_incomplete.as_str()),
+            false => String::new(),
+        }
+    }
+
+    /// Add a torrent file to the client. With `verify` enabled it's added paused and only resumed once a
+    /// recheck finds its data complete.
+    ///
+    /// `replaces_local` is set when the torrent is the local torrent re-added with more trackers. It's the user's
+    /// own torrent with the same data, so it's started right away without a recheck or the incomplete policy.
+    async fn upload_cross_seed_torrent(&self, client: &TorrentClient, hash: &str, mut upload: TorrentUpload, replaces_local: bool) -> Result<InjectionOutcome, CrossSeedError> {
+        let injection = &self.config.injection;
+        let verify = injection.verify && !replaces_local;
+        upload.paused = verify;
+
+        client.add_torrent(&upload).await?;
+
+        // The re-added local torrent has to show up in the client, else the original is restored.
+        if replaces_local && !injection::wait_for_torrent(client, hash, injection).await? {
+            return Err(ClientError::Rpc(format!("{} doesn't have the re-added torrent {}", client.name(), hash)).into());
+        }
+
+        if !verify {
+            return Ok(InjectionOutcome::Added);
+        }
+
+        Ok(injection::verify_injection(client, hash, injection, injection.on_incomplete).await?)
+    }
+
+    /// Check that the content of a local torrent is where its client says it is. This is only possible when the
+    /// client has path mappings, otherwise cross-seed may not see the client's files at all.
+    fn check_local_content(&self, client: &TorrentClient, torrent: &TorrentMetadata, info: &TorrentInfo, save_path: &str) -> Result<(), CrossSeedError> {
+        let content = Path::new(save_path).join(injection::content_name(torrent, info));
+
+        match client.local_path(&content) {
+            Some(local) if !local.exists() => Err(CrossSeedError::MissingContent(local)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Add the found torrent to the client, or its trackers to the local torrent, depending on the torrent mode.
+    async fn inject_cross_seed_torrent(&self, indexer_name: &str, torrent: &TorrentMetadata, found_torrent: Torrent, client: &TorrentClient, info: TorrentInfo) -> Result<InjectionOutcome, CrossSeedError> {
+        // The shutdown waits for this guard, so the injection isn't stopped halfway.
+        let _injecting = self.injections.read().await;
+
+        match self.config.torrent_mode {
+            TorrentMode::InjectTrackers => {
+                if found_torrent.is_private() {
+                    debug!("The found torrent is private, so we must remove the torrent and re-add it with the new trackers...");
+                    self.reinject_private_torrent(indexer_name, torrent, &found_torrent, client, info).await
+                } else {
+                    debug!("Adding trackers to torrent since they aren't private...");
+                    client.add_torrent_trackers(&info, announce_urls(&found_torrent)).await?;
+                    info!("Added trackers of cross-seed torrent {} in {}!", found_torrent.name, client.name());
+
+                    // The local torrent is already seeding, so there is nothing to verify.
+                    Ok(InjectionOutcome::Added)
+                }
+            },
+            TorrentMode::InjectFile => {
+                debug!("Cannot add trackers, uploading new torrent...");
+
+                let (category, tags) = self.cross_seed_labels(indexer_name, client, &info);
+                let mut builder = TorrentUpload::builder()
+                    .category(category)
+                    .tags(tags);
+
+                // Put the cross-seed where the local torrent's content is, not in the default folder of the category.
+                match &info.save_path {
+                    Some(save_path) => {
+                        self.check_local_content(client, torrent, &info, save_path)?;
+
+                        let (save_path, layout) = injection::place_content(torrent, &info, &found_torrent, save_path);
+                        debug!("Adding cross-seed to {} with layout {:?}", save_path, layout);
+                        builder = builder.save_path(save_path).content_layout(layout);
+                    },
+                    None => debug!("{} doesn't report the save path of {}, adding the cross-seed to the default path",
+                        client.name(), torrent.name),
+                }
+
+                // Clone some fields from the torrent due to ownership issues with
+                // found_torrent.encode()
+                let name = found_torrent.name.clone();
+                let hash = found_torrent.info_hash().clone();
+
+                let bytes = found_torrent.encode()?;
+                let upload = builder
+                    .torrent_data(format!("{}.torrent", hash), bytes)
+                    .build();
+
+                let outcome = self.upload_cross_seed_torrent(client, &hash, upload, false).await?;
+                info!("Added cross-seed torrent {} to {}!", name, client.name());
+
+                Ok(outcome)
+            },
+            TorrentMode::Filesystem => {
+                let output = self.config.output_path().ok_or(CrossSeedError::NoOutputPath)?;
+                let path = output.join(format!("{}.torrent", found_torrent.info_hash()));
+                let name = found_torrent.name.clone();
 
-        // Combine both announces and deref the Strings by cloning them.
-        let mut torrent_announces: Vec<String> = torrent_announces.into_iter()
-            .chain(found_announces)
+                tokio::fs::create_dir_all(output).await?;
+                tokio::fs::write(&path, found_torrent.encode()?).await?;
+                info!("Saved cross-seed torrent {} to {}!", name, path.display());
+
+                Ok(InjectionOutcome::Added)
+            },
+        }
+    }
+
+    /// Replace the local torrent with a copy that has the trackers of the private found torrent. This is done as a
+    /// transaction: if the copy can't be added, the original torrent is restored with its category, tags, save path and
+    /// content layout.
+    async fn reinject_private_torrent(&self, indexer_name: &str, torrent: &TorrentMetadata, found_torrent: &Torrent, client: &TorrentClient, info: TorrentInfo) -> Result<InjectionOutcome, CrossSeedError> {
+        // We have to merge the announce urls before we remove the torrent since we retrieve the
+        // urls from the torrent client.
+        let merged = self.merge_torrent_announces(client, torrent, found_torrent).await?;
+        let name = merged.name.clone();
+        let hash = merged.info_hash();
+
+        // Keep the original torrent file to restore it from, the torrent isn't removed without one.
+        let original = match &torrent.path {
+            Some(path) => tokio::fs::read(path).await?,
+            None => return Err(std::io::Error::new(std::io::ErrorKind::NotFound,
+                format!("{} has no .torrent file to restore it from, not removing it", torrent.name)).into()),
+        };
+
+        // The re-added torrent is still the local torrent, so it keeps its own category, tags and save path.
+        let (_, cross_seed_tags) = self.cross_seed_labels(indexer_name, client, &info);
+        let mut tags = info.tags.clone();
+        tags.extend(cross_seed_tags.into_iter().filter(|tag| !info.tags.contains(tag)));
+
+        let layout = injection::local_layout(torrent, &info);
+        let upload = self.local_torrent_upload(&info, &hash, merged.encode()?)
+            .content_layout(layout.clone())
+            .tags(tags)
+            .build();
+
+        client.remove_torrent(&info, false).await?;
+
+        debug!("Re-uploading torrent to client...");
+        match self.upload_cross_seed_torrent(client, &hash, upload, true).await {
+            Ok(outcome) => {
+                info!("Added cross-seed torrent {} to {}!", name, client.name());
+                Ok(outcome)
+            },
+            Err(err) => {
+                error!("Failed to re-add {} to {}, restoring the original torrent: {:?}", name, client.name(), err);
+                self.restore_local_torrent(client, &info, &hash, original, layout).await;
+
+                Err(err)
+            },
+        }
+    }
+
+    /// Start an upload of the local torrent `info` that keeps its category and save path.
+    fn local_torrent_upload(&self, info: &TorrentInfo, hash: &str, bytes: Vec<u8>) -> TorrentUploadBuilder {
+        let mut builder = TorrentUpload::builder()
+            .torrent_data(format!("{}.torrent", hash), bytes);
+
+        if !info.category.is_empty() {
+            builder = builder.category(info.category.clone());
+        }
+        if let Some(save_path) = &info.save_path {
+            builder = builder.save_path(save_path.clone());
+        }
+
+        builder
+    }
+
+    /// Put the original local torrent back after its replacement failed, removing what was added of the replacement.
+    async fn restore_local_torrent(&self, client: &TorrentClient, info: &TorrentInfo, replacement_hash: &str, original: Vec<u8>, layout: ContentLayout) {
+        if let Ok(Some(replacement)) = client.fetch_torrent_info(replacement_hash).await {
+            if let Err(err) = client.remove_torrent(&replacement, false).await {
+                error!("Failed to remove the replacement of {} from {}: {:?}", info.name, client.name(), err);
+            }
+        }
+
+        let upload = self.local_torrent_upload(info, &info.hash, original)
+            .content_layout(layout)
+            .tags(info.tags.clone())
+            .build();
+
+        match client.add_torrent(&upload).await {
+            Ok(()) => info!("Restored the original torrent {} in {}", info.name, client.name()),
+            Err(err) => error!("Failed to restore the original torrent {} in {}, it has to be added again by hand: {:?}",
+                info.name, client.name(), err),
+        }
+    }
+
+    /// Record the decision made for a cross-seed candidate in the decision log. Nothing is
+    /// recorded during a dry run.
+    fn record_decision(&self, record: DecisionRecord) {
+        if self.config.dry_run {
+            return;
+        }
+
+        if let Some(db) = &self.database {
+            if let Err(err) = db.record_decision(&record) {
+                error!("Failed to record decision for {}: {:?}", record.name, err);
+            }
+        }
+    }
+
+    /// Record a cross-seed candidate that couldn't be checked or added because of an error in the decision log,
+    /// and return the error so it can be passed on.
+    fn record_error(&self, mut record: DecisionRecord, err: impl Into<CrossSeedError>) -> CrossSeedError {
+        let err = err.into();
+        record.decision = Decision::Error;
+        record.detail = Some(format!("{:?}", err));
+        self.record_decision(record);
+
+        err
+    }
+
+    /// Check a release from an indexer against the local library. If it could be the same
+    /// content as a local torrent, the release is downloaded, verified and added as a cross-seed.
+    ///
+    /// The torrent file is downloaded with `http`, so it should be the client of the indexer.
+    pub async fn match_release(&self, indexer_name: &str, http: &reqwest::Client, release: &TorrentResult) -> Result<MatchOutcome, CrossSeedError> {
+        // Find local torrents that could match before downloading anything from the indexer.
+        let candidates: Vec<LibraryEntry> = self.library.read().await
+            .find_matches(&release.name, release.size)
+            .into_iter()
             .cloned()
             .collect();
-        // Remove the [DHT], [PeX] and [LSD] announces from the list.
-        // The client should handle those.
-        torrent_announces.retain(|announce| !(announce.starts_with("** [") && announce.ends_with("] **")));
 
-        // Copy the torrent file and add the announces to it.
-        // Additionally, add the private field to the torrent.
-        let mut torrent = torrent.clone();
-        torrent.announce_list = Some(vec![torrent_announces]);
-        if let Some(extra) = torrent.extra_info_fields.as_mut() {
-            extra.insert(String::from("private"), BencodeElem::Integer(1));
-        } else {
-            let mut extra = std::collections::HashMap::new();
-            extra.insert(String::from("private"), BencodeElem::Integer(1));
-            torrent.extra_info_fields = Some(extra);
+        if candidates.is_empty() {
+            return Ok(MatchOutcome::NoMatch);
+        }
+
+        debug!("Release {} may match {} local torrents, downloading it...", release.name, candidates.len());
+        let found_torrent = release.download_torrent(http).await?;
+        // Whether a matching local torrent wasn't found in the clients, so the release can't be ruled out.
+        let mut missing = false;
+
+        for entry in candidates.iter() {
+            let record = |decision| DecisionRecord::new(&entry.info_hash, &entry.name, indexer_name, &found_torrent, decision);
+
+            if found_torrent.info_hash() == entry.info_hash {
+                debug!("Release {} is the same torrent as the local one, skipping...", release.name);
+                self.record_decision(record(Decision::SameInfoHash));
+                continue;
+            }
+
+            if entry.total_size != found_torrent.length as u64 {
+                debug!("Size of release {} doesn't match the local torrent, skipping...", release.name);
+                self.record_decision(record(Decision::SizeMismatch));
+                continue;
+            }
+
+            if !entry.files_match(&found_torrent) {
+                debug!("Files of release {} don't match the local torrent, skipping...", release.name);
+                self.record_decision(record(Decision::FileTreeMismatch));
+                continue;
+            }
+
+            let already_seeding = self.torrent_clients.has_exact_torrent(&found_torrent.info_hash()).await
+                .map_err(|err| self.record_error(record(Decision::Error), err))?;
+            if already_seeding {
+                info!("Already cross-seeding {} (with a separate torrent file), skipping...", release.name);
+                self.record_decision(record(Decision::AlreadySeeding));
+                return Ok(MatchOutcome::AlreadySeeding);
+            }
+
+            // Only read the local torrent now that it's needed for adding the cross-seed.
+            let torrent = entry.load_metadata()
+                .map_err(|err| self.record_error(record(Decision::Error), err))?;
+            let found = self.torrent_clients.find_torrent(&torrent.info_hash).await
+                .map_err(|err| self.record_error(record(Decision::Error), err))?;
+            match found {
+                Some((client, info)) => {
+                    info!("Found cross-seed for {} on {}", torrent.name, indexer_name);
+                    return match self.add_cross_seed_torrent(indexer_name, &torrent, found_torrent, client, info).await? {
+                        Decision::Injected => Ok(MatchOutcome::Added),
+                        Decision::DryRun => Ok(MatchOutcome::WouldAdd),
+                        Decision::NotComplete | Decision::Error => Ok(MatchOutcome::NotAdded),
+                        _ => Ok(MatchOutcome::NoMatch),
+                    };
+                },
+                None => {
+                    error!("Failed to find torrent in any client!");
+                    missing = true;
+                },
+            }
         }
 
+        Ok(if missing { MatchOutcome::NotAdded } else { MatchOutcome::NoMatch })
+    }
+
+    /// Merge two torrent's announce urls into one torrent. The announce urls of the local torrent are read from
+    /// the client holding it, and the full local torrent is reloaded from its file to be encoded again.
+    pub async fn merge_torrent_announces(&self, client: &TorrentClient, torrent: &TorrentMetadata, found_torrent: &Torrent) -> Result<Torrent, CrossSeedError> {
+        // Get announce urls of both torrents.
+        let request_info = TorrentInfo::from_hash(torrent.info_hash.clone());
+        let torrent_announces = merge_announces(client.get_torrent_trackers(&request_info).await?, announce_urls(found_torrent));
+
+        // Copy the torrent file and add the announces to it. The info dict is left alone, changing it
+        // would change the info hash and the client wouldn't recognize the local data anymore.
+        let mut torrent = torrent.load_torrent()?;
+        if torrent.announce.is_none() {
+            torrent.announce = torrent_announces.first().cloned();
+        }
+        torrent.announce_list = Some(vec![torrent_announces]);
+
         Ok(torrent)
     }
 
     /// Searches for a torrent in another indexer. Will return the found torrent.
-    pub async fn search_for_cross_torrent(&self, indexer: &Indexer, torrent: &Torrent, info: TorrentInfo) -> Result<Option<Torrent>, CrossSeedError> {
-        if let Some(found_torrent) = indexer.search_indexer(&torrent).await? {
+    pub async fn search_for_cross_torrent(&self, indexer: &Indexer, torrent: &TorrentMetadata, client: &TorrentClient, info: TorrentInfo) -> Result<Option<Torrent>, CrossSeedError> {
+        if let Some(found_torrent) = indexer.search_indexer(torrent).await? {
+            let hash = &torrent.info_hash;
+            let record = |decision| DecisionRecord::new(hash, &torrent.name, &indexer.name, &found_torrent, decision);
 
             // Check if we found the same torrent in its own indexer
-            if found_torrent.info_hash() == torrent.info_hash() {
+            if found_torrent.info_hash() == *hash {
                 debug!("Found same torrent in its own indexer, skipping...");
+                self.record_decision(record(Decision::SameInfoHash));
+                return Ok(None);
+            }
+
+            // Check if the found torrent has the same content as ours.
+            if found_torrent.length as u64 != torrent.length {
+                debug!("Size of the found torrent doesn't match, skipping...");
+                self.record_decision(record(Decision::SizeMismatch));
+                return Ok(None);
+            }
+
+            if library::torrent_files(&found_torrent) != torrent.files {
+                debug!("Files of the found torrent don't match, skipping...");
+                self.record_decision(record(Decision::FileTreeMismatch));
                 return Ok(None);
             }
 
-            // Check if we're already seeding this specific torrent file.
-            if self.torrent_client.has_exact_torrent(&found_torrent).await? {
+            // Check if we're already seeding this specific torrent file in any client.
+            let already_seeding = self.torrent_clients.has_exact_torrent(&found_torrent.info_hash()).await
+                .map_err(|err| self.record_error(record(Decision::Error), err))?;
+            if already_seeding {
                 info!("Already cross-seeding to this tracker (with a separate torrent file), skipping...");
+                self.record_decision(record(Decision::AlreadySeeding));
                 return Ok(None); 
             }
 
-            if let Some(found_announces) = &found_torrent.announce_list {
-                // Some urls can be encoded so we need to decode to compare them.
-                let found_announces: Vec<Vec<String>> = found_announces.iter()
-                    .map(|a_list| 
-                        a_list.iter().map(|a| urlencoding::decode(a)
-                                .unwrap().to_string())
-                            .collect::<Vec<String>>())
-                    .collect();
+            // Some urls can be encoded so we need to decode to compare them.
+            let found_announces: Vec<String> = announce_urls(&found_torrent).into_iter()
+                .map(|announce| urlencoding::decode(&announce).map_or_else(|_| announce.clone(), |decoded| decoded.to_string()))
+                .collect();
 
+            // A torrent without trackers can't be cross-seeded to a tracker.
+            if !found_announces.is_empty() {
                 // Get the trackers of the torrent from the download client.
-                let torrent_announces = self.torrent_client.get_torrent_trackers(&info).await.unwrap(); // TODO: Remove
-                let torrent_announces: Vec<&String> = torrent_announces.iter().map(|t| &t.url).collect();
-
-                // Flatten the announce list to make them easier to search.
-                let found_announces: Vec<&String> = found_announces.iter()
-                    .flat_map(|array| array.iter())
-                    .collect();
+                let torrent_announces = client.get_torrent_trackers(&info).await
+                    .map_err(|err| self.record_error(record(Decision::Error), err))?;
 
                 // Check if the client has the trackers of the torrent already.
                 let client_has_trackers = found_announces.iter()
@@ -230,6 +689,7 @@ impl CrossSeed {
                     return Ok(Some(found_torrent));
                 } else {
                     info!("Already cross seeding to this tracker, skipping...");
+                    self.record_decision(record(Decision::AlreadySeeding));
                 }
             }
         }
@@ -238,10 +698,56 @@ impl CrossSeed {
     }
 }
 
+/// The announce urls of a torrent in tier order. A torrent without an announce list only has its announce url.
+fn announce_urls(torrent: &Torrent) -> Vec<String> {
+    match (&torrent.announce_list, &torrent.announce) {
+        (Some(list), _) => list.iter().flatten().cloned().collect(),
+        (None, Some(announce)) => vec![announce.clone()],
+        (None, None) => vec![],
+    }
+}
+
+/// Combine the announce urls of the local torrent in its client with the ones of the found torrent, without
+/// duplicates. The [DHT], [PeX] and [LSD] entries clients report are dropped, the client handles those.
+fn merge_announces(local: Vec<String>, found: Vec<String>) -> Vec<String> {
+    let mut announces: Vec<String> = vec![];
+
+    for announce in local.into_iter().chain(found) {
+        if !(announce.starts_with("** [") && announce.ends_with("] **")) && !announces.contains(&announce) {
+            announces.push(announce);
+        }
+    }
+
+    announces
+}
+
+/// The outcome of checking a release against the local library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchOutcome {
+    /// The release was added as a cross-seed.
+    Added,
+    /// The release would have been added as a cross-seed, but it's a dry run.
+    WouldAdd,
+    /// The release is already in the client.
+    AlreadySeeding,
+    /// The release doesn't match any local torrent.
+    NoMatch,
+    /// The release matches a local torrent, but it couldn't be added yet. The local torrent isn't done
+    /// downloading or is missing from the clients, or adding the cross-seed failed.
+    NotAdded,
+}
+
 #[derive(Debug)]
 pub enum CrossSeedError {
     TorznabClient(crate::torznab::ClientError),
-    TorrentClient(abstracttorrent::error::ClientError),
+    TorrentClient(crate::torrent_client::ClientError),
+    TorrentError(lava_torrent::LavaTorrentError),
+    Database(DatabaseError),
+    /// The content of a local torrent wasn't found at the path its client reports, after path mapping.
+    MissingContent(PathBuf),
+    /// The torrent mode saves torrents to the filesystem, but no output path is configured.
+    NoOutputPath,
+    IoError(std::io::Error),
 }
 
 impl From<crate::torznab::ClientError> for CrossSeedError {
@@ -250,8 +756,76 @@ impl From<crate::torznab::ClientError> for CrossSeedError {
     }
 }
 
-impl From<abstracttorrent::error::ClientError> for CrossSeedError {
-    fn from(err: abstracttorrent::error::ClientError) -> Self {
+impl From<crate::torrent_client::ClientError> for CrossSeedError {
+    fn from(err: crate::torrent_client::ClientError) -> Self {
         Self::TorrentClient(err)
     }
-}
\ No newline at end of file
+}
+
+impl From<lava_torrent::LavaTorrentError> for CrossSeedError {
+    fn from(err: lava_torrent::LavaTorrentError) -> Self {
+        Self::TorrentError(err)
+    }
+}
+
+impl From<DatabaseError> for CrossSeedError {
+    fn from(err: DatabaseError) -> Self {
+        Self::Database(err)
+    }
+}
+
+impl From<std::io::Error> for CrossSeedError {
+    fn from(err: std::io::Error) -> Self {
+        Self::IoError(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A single file torrent with `announce` and an optional announce list of one tier.
+    fn torrent(announce: &str, announce_list: Option<&[&str]>) -> Torrent {
+        let list = match announce_list {
+            Some(urls) => {
+                let urls: String = urls.iter().map(|url| format!("{}:{}", url.len(), url)).collect();
+                format!("13:announce-listll{}ee", urls)
+            },
+            None => String::new(),
+        };
+        let bytes = format!("d8:announce{}:{}{}4:infod6:lengthi10e4:name8:file.mkv12:piece lengthi16384e6:pieces20:{}ee",
+            announce.len(), announce, list, "a".repeat(20));
+
+        Torrent::read_from_bytes(bytes.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn announce_urls_fall_back_to_announce() {
+        let single = torrent("https://tracker.example/announce", None);
+        assert_eq!(announce_urls(&single), vec!["https://tracker.example/announce"]);
+
+        let listed = torrent("https://a.example/announce", Some(&["https://a.example/announce", "https://b.example/announce"]));
+        assert_eq!(announce_urls(&listed), vec!["https://a.example/announce", "https://b.example/announce"]);
+    }
+
+    #[test]
+    fn merge_announces_of_announce_only_torrent() {
+        let found = torrent("https://private.example/announce/key", None);
+        let local = vec![
+            String::from("** [DHT] **"),
+            String::from("https://public.example/announce"),
+            String::from("** [PeX] **"),
+        ];
+
+        assert_eq!(merge_announces(local, announce_urls(&found)),
+            vec!["https://public.example/announce", "https://private.example/announce/key"]);
+    }
+
+    #[test]
+    fn merge_announces_skips_duplicates() {
+        let local = vec![String::from("https://a.example/announce")];
+        let found = vec![String::from("https://a.example/announce"), String::from("https://b.example/announce")];
+
+        assert_eq!(merge_announces(local, found), vec!["https://a.example/announce", "https://b.example/announce"]);
+    }
+}
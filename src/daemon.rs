@@ -0,0 +1,327 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::{mpsc, watch, Mutex};
+use tracing::{debug, error, info, warn};
+
+use crate::completion::CompletionWatcher;
+use crate::cross_seed::CrossSeed;
+use crate::rss_poller::RssPoller;
+
+/// How long to wait for the running jobs to finish on shutdown. A running search stops between the searches
+/// of a torrent on each indexer, and cross-seeds that are being added are always waited for.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// When the full library search is scheduled to run.
+enum SearchSchedule {
+    Interval(Duration),
+    Cron(cron::Schedule),
+}
+
+impl SearchSchedule {
+    /// How long to wait until the next search.
+    fn next_delay(&self) -> Option<Duration> {
+        match self {
+            SearchSchedule::Interval(interval) => Some(*interval),
+            SearchSchedule::Cron(schedule) => schedule.upcoming(chrono::Local)
+                .next()
+                .and_then(|next| (next - chrono::Local::now()).to_std().ok()),
+        }
+    }
+}
+
+/// The start and end times of a full library search, as unix timestamps.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SearchRun {
+    pub started_at: i64,
+    /// `None` while the search is running.
+    pub finished_at: Option<i64>,
+}
+
+/// Runs cross-seed as a long running process. Full library searches, rss polls and
+/// housekeeping are each run on their own schedule until the process is told to stop.
+pub struct Daemon {
+    seed: Arc<CrossSeed>,
+    rss: RssPoller,
+    completion: CompletionWatcher,
+    /// Held while a full library search is running so searches never overlap.
+    search_lock: Mutex<()>,
+    last_search: std::sync::Mutex<Option<SearchRun>>,
+    /// Info hashes of single torrents waiting to be searched.
+    queue: mpsc::UnboundedSender<String>,
+    queue_receiver: Mutex<Option<mpsc::UnboundedReceiver<String>>>,
+    queued: AtomicUsize,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Daemon {
+    pub fn new(seed: Arc<CrossSeed>) -> Self {
+        let (shutdown, _) = watch::channel(false);
+        let (queue, queue_receiver) = mpsc::unbounded_channel();
+
+        Self {
+            rss: RssPoller::new(Arc::clone(&seed)),
+            completion: CompletionWatcher::new(Arc::clone(seed.torrent_clients())),
+            seed,
+            search_lock: Mutex::new(()),
+            last_search: std::sync::Mutex::new(None),
+            queue,
+            queue_receiver: Mutex::new(Some(queue_receiver)),
+            queued: AtomicUsize::new(0),
+            shutdown,
+        }
+    }
+
+    /// Run the daemon until a SIGTERM or ctrl-c is received.
+    pub async fn run(self) {
+        let daemon = Arc::new(self);
+        let config = daemon.seed.config();
+
+        let mut handles = vec![];
+
+        if let Some(server) = &config.server {
+            handles.push(tokio::spawn(crate::server::serve(server.clone(), Arc::clone(&daemon), daemon.shutdown.subscribe())));
+        }
+
+        let queue_daemon = Arc::clone(&daemon);
+        handles.push(tokio::spawn(async move { queue_daemon.run_queue().await }));
+
+        let search_daemon = Arc::clone(&daemon);
+        handles.push(tokio::spawn(async move { search_daemon.run_searches().await }));
+
+        if let Some(interval) = config.rss_interval {
+            info!("Polling rss feeds every {:?}", interval);
+
+            let rss_daemon = Arc::clone(&daemon);
+            handles.push(tokio::spawn(async move { rss_daemon.run_rss(interval).await }));
+        }
+
+        if let Some(interval) = config.daemon.completion_interval {
+            info!("Watching for completed torrents every {:?}", interval);
+
+            let completion_daemon = Arc::clone(&daemon);
+            handles.push(tokio::spawn(async move { completion_daemon.run_completion(interval).await }));
+        }
+
+        if let Some(interval) = config.daemon.housekeeping_interval {
+            let housekeeping_daemon = Arc::clone(&daemon);
+            handles.push(tokio::spawn(async move { housekeeping_daemon.run_housekeeping(interval).await }));
+        }
+
+        wait_for_shutdown_signal().await;
+        info!("Shutting down, waiting for running jobs to finish...");
+
+        // Sending only fails when every task has already exited.
+        daemon.shutdown.send(true).ok();
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, futures::future::join_all(handles)).await.is_err() {
+            warn!("Running jobs didn't finish within {:?}, stopping them", SHUTDOWN_TIMEOUT);
+        }
+
+        // A cross-seed that is being added is never stopped halfway, the guard is held until the process exits.
+        let _injections = daemon.seed.stop_injections().await;
+
+        if !config.dry_run {
+            if let Err(err) = daemon.seed.library().read().await.save(&config.library_path()) {
+                error!("Failed to save library index: {:?}", err);
+            }
+        }
+
+        info!("Shut down cleanly");
+    }
+
+    pub fn seed(&self) -> &Arc<CrossSeed> {
+        &self.seed
+    }
+
+    /// Check if a full library search is running.
+    pub fn is_searching(&self) -> bool {
+        self.search_lock.try_lock().is_err()
+    }
+
+    /// Get the last full library search, or the running one.
+    pub fn last_search(&self) -> Option<SearchRun> {
+        *self.last_search.lock().unwrap()
+    }
+
+    /// Queue a single torrent to be searched for.
+    pub fn queue_search(&self, info_hash: String) {
+        self.queued.fetch_add(1, Ordering::SeqCst);
+
+        // Sending only fails when the queue is no longer being processed.
+        if self.queue.send(info_hash).is_err() {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// The amount of single torrents waiting to be searched for.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Get the schedule of full library searches from the config.
+    fn search_schedule(&self) -> Option<SearchSchedule> {
+        let config = &self.seed.config().daemon;
+
+        if let Some(expression) = &config.search_cron {
+            match cron::Schedule::from_str(expression) {
+                Ok(schedule) => return Some(SearchSchedule::Cron(schedule)),
+                Err(err) => error!("Invalid search cron expression `{}`: {}", expression, err),
+            }
+        }
+
+        config.search_interval.map(SearchSchedule::Interval)
+    }
+
+    /// Check if scheduled work is allowed to run right now.
+    fn is_in_allowed_window(&self) -> bool {
+        self.seed.config().daemon.is_allowed_at(chrono::Local::now().time())
+    }
+
+    /// Sleep for a duration, returning false if the daemon is shutting down.
+    async fn sleep(&self, duration: Duration, shutdown: &mut watch::Receiver<bool>) -> bool {
+        if *shutdown.borrow() {
+            return false;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(duration) => true,
+            _ = shutdown.changed() => false,
+        }
+    }
+
+    /// Search the whole library, unless a search is already running.
+    pub async fn search_library(&self) {
+        let _guard = match self.search_lock.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => {
+                info!("A library search is already running, skipping...");
+                return;
+            }
+        };
+
+        info!("Starting library search...");
+        let started_at = crate::util::unix_timestamp();
+        *self.last_search.lock().unwrap() = Some(SearchRun { started_at, finished_at: None });
+
+        crate::search::search_library(&self.seed, self.shutdown.subscribe()).await;
+
+        *self.last_search.lock().unwrap() = Some(SearchRun { started_at, finished_at: Some(crate::util::unix_timestamp()) });
+        info!("Finished library search");
+    }
+
+    /// Search the library once on startup, and then on its schedule.
+    async fn run_searches(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+        let schedule = self.search_schedule();
+
+        if schedule.is_none() {
+            warn!("No search interval or cron expression is set, the library will only be searched on startup.");
+        }
+
+        let mut delay = Some(Duration::ZERO);
+        while let Some(wait) = delay {
+            if !self.sleep(wait, &mut shutdown).await {
+                break;
+            }
+
+            if self.is_in_allowed_window() {
+                self.search_library().await;
+            } else {
+                debug!("Outside of the allowed time windows, skipping library search...");
+            }
+
+            delay = schedule.as_ref().and_then(|schedule| schedule.next_delay());
+        }
+    }
+
+    /// Poll the rss feeds of the indexers on an interval.
+    async fn run_rss(&self, interval: Duration) {
+        let mut shutdown = self.shutdown.subscribe();
+
+        while self.sleep(interval, &mut shutdown).await {
+            if self.is_in_allowed_window() {
+                debug!("Polling rss feeds...");
+                self.rss.poll().await;
+            } else {
+                debug!("Outside of the allowed time windows, skipping rss poll...");
+            }
+        }
+    }
+
+    /// Poll the client for torrents that finished downloading on an interval, and queue
+    /// searches for them.
+    async fn run_completion(&self, interval: Duration) {
+        let mut shutdown = self.shutdown.subscribe();
+
+        while self.sleep(interval, &mut shutdown).await {
+            match self.completion.poll().await {
+                Ok(hashes) => for hash in hashes {
+                    debug!("Torrent {} finished downloading, queueing search...", hash);
+                    self.queue_search(hash);
+                },
+                Err(err) => error!("Failed to poll client for completed torrents: {:?}", err),
+            }
+        }
+    }
+
+    /// Search for the queued single torrents in the order they were queued.
+    async fn run_queue(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+        let mut queue = match self.queue_receiver.lock().await.take() {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        loop {
+            let info_hash = tokio::select! {
+                info_hash = queue.recv() => info_hash,
+                _ = shutdown.changed() => None,
+            };
+            let info_hash = match info_hash {
+                Some(info_hash) => info_hash,
+                None => break,
+            };
+
+            if let Err(err) = crate::search::search_info_hash(&self.seed, &info_hash, &shutdown).await {
+                error!("Failed to search for queued torrent {}: {:?}", info_hash, err);
+            }
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Refresh and save the library index on an interval, so releases from rss and announces
+    /// are matched against torrents added since the last search.
+    async fn run_housekeeping(&self, interval: Duration) {
+        let mut shutdown = self.shutdown.subscribe();
+
+        while self.sleep(interval, &mut shutdown).await {
+            // The library is already refreshed by a running search.
+            if let Ok(_guard) = self.search_lock.try_lock() {
+                debug!("Running housekeeping...");
+                crate::search::refresh_library(&self.seed).await;
+            }
+        }
+    }
+}
+
+/// Wait until the process is told to stop with SIGTERM or ctrl-c.
+async fn wait_for_shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM");
+
+        tokio::select! {
+            _ = terminate.recv() => {},
+            _ = tokio::signal::ctrl_c() => {},
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
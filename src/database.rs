@@ -0,0 +1,394 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lava_torrent::torrent::v1::Torrent;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+
+/// The outcome of searching an indexer for a torrent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchOutcome {
+    /// A cross-seed candidate was found.
+    Found,
+    /// Nothing usable was found.
+    NotFound,
+    /// The search failed.
+    Error,
+}
+
+impl SearchOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchOutcome::Found => "found",
+            SearchOutcome::NotFound => "not_found",
+            SearchOutcome::Error => "error",
+        }
+    }
+}
+
+/// The decision that was made for a cross-seed candidate of a local torrent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// The candidate is the local torrent itself.
+    SameInfoHash,
+    /// The candidate, or its trackers, are already in the client.
+    AlreadySeeding,
+    /// The total size of the candidate doesn't match the local torrent.
+    SizeMismatch,
+    /// The files of the candidate don't match the files of the local torrent.
+    FileTreeMismatch,
+    /// The local torrent isn't done downloading.
+    NotComplete,
+    /// The candidate was added as a cross-seed.
+    Injected,
+    /// The candidate was added, but wasn't complete after its recheck so it isn't seeding.
+    Incomplete,
+    /// The candidate would have been added, but it's a dry run.
+    DryRun,
+    /// Adding the candidate failed.
+    Error,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Decision::SameInfoHash => "same_info_hash",
+            Decision::AlreadySeeding => "already_seeding",
+            Decision::SizeMismatch => "size_mismatch",
+            Decision::FileTreeMismatch => "file_tree_mismatch",
+            Decision::NotComplete => "not_complete",
+            Decision::Injected => "injected",
+            Decision::Incomplete => "incomplete",
+            Decision::DryRun => "dry_run",
+            Decision::Error => "error",
+        }
+    }
+
+    pub fn parse(decision: &str) -> Option<Self> {
+        match decision {
+            "same_info_hash" => Some(Decision::SameInfoHash),
+            "already_seeding" => Some(Decision::AlreadySeeding),
+            "size_mismatch" => Some(Decision::SizeMismatch),
+            "file_tree_mismatch" => Some(Decision::FileTreeMismatch),
+            "not_complete" => Some(Decision::NotComplete),
+            "injected" => Some(Decision::Injected),
+            "incomplete" => Some(Decision::Incomplete),
+            "dry_run" => Some(Decision::DryRun),
+            "error" => Some(Decision::Error),
+            _ => None,
+        }
+    }
+}
+
+/// A decision made for a cross-seed candidate found on an indexer for a local torrent.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecisionRecord {
+    pub created_at: i64,
+    /// Info hash of the local torrent.
+    pub info_hash: String,
+    /// Name of the local torrent.
+    pub name: String,
+    pub indexer: String,
+    pub candidate_name: String,
+    pub candidate_hash: String,
+    pub decision: Decision,
+    /// Extra information about the decision, like the error that occurred.
+    pub detail: Option<String>,
+}
+
+impl DecisionRecord {
+    pub fn new(info_hash: &str, name: &str, indexer: &str, candidate: &Torrent, decision: Decision) -> Self {
+        Self {
+            created_at: crate::util::unix_timestamp(),
+            info_hash: info_hash.to_string(),
+            name: name.to_string(),
+            indexer: indexer.to_string(),
+            candidate_name: candidate.name.clone(),
+            candidate_hash: candidate.info_hash(),
+            decision,
+            detail: None,
+        }
+    }
+}
+
+/// Filters for querying the decision log. Filters that are `None` match everything.
+#[derive(Debug, Clone, Default)]
+pub struct DecisionQuery {
+    /// Only match local torrents whose name contains this.
+    pub name: Option<String>,
+    pub info_hash: Option<String>,
+    pub indexer: Option<String>,
+    pub decision: Option<Decision>,
+    /// The maximum amount of decisions to return, newest first.
+    pub limit: Option<u32>,
+}
+
+/// A local sqlite database that stores the history of cross-seed.
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    /// Open the database at a path, creating it and its tables if they don't exist.
+    pub fn open(path: &Path) -> Result<Self, DatabaseError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS searches (
+                info_hash TEXT NOT NULL,
+                indexer TEXT NOT NULL,
+                first_searched INTEGER NOT NULL,
+                last_searched INTEGER NOT NULL,
+                outcome TEXT NOT NULL,
+                PRIMARY KEY (info_hash, indexer)
+            );
+            CREATE TABLE IF NOT EXISTS decisions (
+                id INTEGER PRIMARY KEY,
+                created_at INTEGER NOT NULL,
+                info_hash TEXT NOT NULL,
+                name TEXT NOT NULL,
+                indexer TEXT NOT NULL,
+                candidate_name TEXT NOT NULL,
+                candidate_hash TEXT NOT NULL,
+                decision TEXT NOT NULL,
+                detail TEXT
+            );
+            CREATE INDEX IF NOT EXISTS decisions_info_hash ON decisions (info_hash);"
+        )?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Open an existing database without writing to it, ex: for a dry run. Returns `None` if there is no
+    /// database yet, nothing is created then.
+    pub fn open_read_only(path: &Path) -> Result<Option<Self>, DatabaseError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+        Ok(Some(Self {
+            conn: Mutex::new(conn),
+        }))
+    }
+
+    /// Record that a torrent was searched for on an indexer.
+    ///
+    /// A failed search doesn't count as a search, so it doesn't stop the torrent from being searched again.
+    /// Only the outcome of a torrent that was searched before is updated.
+    pub fn record_search(&self, info_hash: &str, indexer: &str, outcome: SearchOutcome) -> Result<(), DatabaseError> {
+        let now = crate::util::unix_timestamp();
+
+        if outcome == SearchOutcome::Error {
+            self.conn.lock().unwrap().execute(
+                "UPDATE searches SET outcome = ?3 WHERE info_hash = ?1 AND indexer = ?2",
+                params![info_hash, indexer, outcome.as_str()],
+            )?;
+
+            return Ok(());
+        }
+
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO searches (info_hash, indexer, first_searched, last_searched, outcome)
+                VALUES (?1, ?2, ?3, ?3, ?4)
+                ON CONFLICT (info_hash, indexer) DO UPDATE SET last_searched = ?3, outcome = ?4",
+            params![info_hash, indexer, now, outcome.as_str()],
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the timestamps of when a torrent was first and last searched for on an indexer.
+    pub fn get_search_times(&self, info_hash: &str, indexer: &str) -> Result<Option<(i64, i64)>, DatabaseError> {
+        let times = self.conn.lock().unwrap().query_row(
+            "SELECT first_searched, last_searched FROM searches WHERE info_hash = ?1 AND indexer = ?2",
+            params![info_hash, indexer],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).optional()?;
+
+        Ok(times)
+    }
+
+    /// Check if a torrent should be searched for on an indexer.
+    ///
+    /// Torrents aren't searched if they were searched less than `exclude_recent_search` ago,
+    /// or if they were first searched more than `exclude_older` ago.
+    pub fn should_search(&self, info_hash: &str, indexer: &str, exclude_recent_search: Option<Duration>, exclude_older: Option<Duration>) -> Result<bool, DatabaseError> {
+        let (first_searched, last_searched) = match self.get_search_times(info_hash, indexer)? {
+            Some(times) => times,
+            None => return Ok(true),
+        };
+        let now = crate::util::unix_timestamp();
+
+        if let Some(recent) = exclude_recent_search {
+            if now - last_searched < recent.as_secs() as i64 {
+                return Ok(false);
+            }
+        }
+
+        if let Some(older) = exclude_older {
+            if now - first_searched > older.as_secs() as i64 {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Record a decision made for a cross-seed candidate.
+    pub fn record_decision(&self, record: &DecisionRecord) -> Result<(), DatabaseError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO decisions (created_at, info_hash, name, indexer, candidate_name, candidate_hash, decision, detail)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                record.created_at,
+                record.info_hash,
+                record.name,
+                record.indexer,
+                record.candidate_name,
+                record.candidate_hash,
+                record.decision.as_str(),
+                record.detail,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the decisions matching a query, newest first.
+    pub fn query_decisions(&self, query: &DecisionQuery) -> Result<Vec<DecisionRecord>, DatabaseError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT created_at, info_hash, name, indexer, candidate_name, candidate_hash, decision, detail
+                FROM decisions
+                WHERE (?1 IS NULL OR name LIKE '%' || ?1 || '%')
+                    AND (?2 IS NULL OR info_hash = lower(?2))
+                    AND (?3 IS NULL OR indexer = ?3 COLLATE NOCASE)
+                    AND (?4 IS NULL OR decision = ?4)
+                ORDER BY created_at DESC, id DESC
+                LIMIT ?5"
+        )?;
+
+        // A negative limit means no limit in sqlite.
+        let limit = query.limit.map_or(-1, i64::from);
+        let decision = query.decision.map(|decision| decision.as_str());
+        let rows = stmt.query_map(params![query.name, query.info_hash, query.indexer, decision, limit], |row| {
+            let decision: String = row.get(6)?;
+
+            Ok(DecisionRecord {
+                created_at: row.get(0)?,
+                info_hash: row.get(1)?,
+                name: row.get(2)?,
+                indexer: row.get(3)?,
+                candidate_name: row.get(4)?,
+                candidate_hash: row.get(5)?,
+                decision: Decision::parse(&decision).unwrap_or(Decision::Error),
+                detail: row.get(7)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    SqliteError(rusqlite::Error),
+    IoError(std::io::Error),
+}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(e: rusqlite::Error) -> Self {
+        DatabaseError::SqliteError(e)
+    }
+}
+
+impl From<std::io::Error> for DatabaseError {
+    fn from(e: std::io::Error) -> Self {
+        DatabaseError::IoError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn open(name: &str) -> Database {
+        let path = std::env::temp_dir().join(format!("crate-{}-{}.db", name, std::process::id()));
+        std::fs::remove_file(&path).ok();
+
+        Database::open(&path).unwrap()
+    }
+
+    /// Move the search times of a torrent back by some days.
+    fn set_search_days_ago(db: &Database, info_hash: &str, first: i64, last: i64) {
+        let now = crate::util::unix_timestamp();
+        let day = DAY.as_secs() as i64;
+
+        db.conn.lock().unwrap().execute(
+            "UPDATE searches SET first_searched = ?2, last_searched = ?3 WHERE info_hash = ?1",
+            params![info_hash, now - first * day, now - last * day],
+        ).unwrap();
+    }
+
+    #[test]
+    fn searches_torrents_outside_of_windows() {
+        let db = open("windows");
+        assert!(db.should_search("a", "indexer", Some(HOUR), Some(DAY)).unwrap());
+
+        db.record_search("a", "indexer", SearchOutcome::NotFound).unwrap();
+        assert!(!db.should_search("a", "indexer", Some(HOUR), None).unwrap());
+        assert!(db.should_search("a", "indexer", None, None).unwrap());
+        assert!(db.should_search("a", "other", Some(HOUR), None).unwrap());
+
+        // Searched 2 days ago for the first time, and 1 day ago for the last time.
+        set_search_days_ago(&db, "a", 2, 1);
+        assert!(db.should_search("a", "indexer", Some(HOUR), None).unwrap());
+        assert!(!db.should_search("a", "indexer", Some(2 * DAY), None).unwrap());
+        assert!(!db.should_search("a", "indexer", None, Some(DAY)).unwrap());
+        assert!(db.should_search("a", "indexer", None, Some(3 * DAY)).unwrap());
+    }
+
+    #[test]
+    fn failed_searches_are_searched_again() {
+        let db = open("failed");
+
+        db.record_search("a", "indexer", SearchOutcome::Error).unwrap();
+        assert_eq!(db.get_search_times("a", "indexer").unwrap(), None);
+        assert!(db.should_search("a", "indexer", Some(HOUR), Some(DAY)).unwrap());
+
+        db.record_search("a", "indexer", SearchOutcome::Found).unwrap();
+        set_search_days_ago(&db, "a", 2, 2);
+        db.record_search("a", "indexer", SearchOutcome::Error).unwrap();
+        assert!(db.should_search("a", "indexer", Some(HOUR), None).unwrap());
+    }
+
+    #[test]
+    fn read_only_leaves_data_dir_untouched() {
+        let dir = std::env::temp_dir().join(format!("crate-dry-run-{}", std::process::id())).join("data");
+        std::fs::remove_dir_all(&dir).ok();
+        let path = dir.join("cross-seed.db");
+
+        assert!(Database::open_read_only(&path).unwrap().is_none());
+        assert!(!dir.exists());
+
+        // An existing history is still used to skip searches.
+        Database::open(&path).unwrap().record_search("a", "indexer", SearchOutcome::NotFound).unwrap();
+        let db = Database::open_read_only(&path).unwrap().unwrap();
+        assert!(!db.should_search("a", "indexer", Some(HOUR), None).unwrap());
+        assert!(db.record_search("b", "indexer", SearchOutcome::NotFound).is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
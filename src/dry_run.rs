@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+
+use tracing::info;
+
+/// Something cross-seed would have done to the torrent client or filesystem if it wasn't a dry run.
+#[derive(Debug, Clone)]
+pub struct PlannedAction {
+    /// Name of the local torrent.
+    pub name: String,
+    pub indexer: String,
+    /// Name of the cross-seed candidate.
+    pub candidate_name: String,
+    /// A description of the action, ex: `add 3 trackers to the local torrent`.
+    pub action: String,
+}
+
+/// The actions that were skipped during a dry run.
+#[derive(Debug, Default)]
+pub struct DryRunReport {
+    actions: Mutex<Vec<PlannedAction>>,
+}
+
+impl DryRunReport {
+    pub fn record(&self, action: PlannedAction) {
+        info!("[dry run] Would {} (cross-seed of {} from {})", action.action, action.name, action.indexer);
+        self.actions.lock().unwrap().push(action);
+    }
+
+    /// Take the recorded actions, leaving the report empty.
+    pub fn take(&self) -> Vec<PlannedAction> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+
+    /// Print the recorded actions and empty the report.
+    pub fn print(&self, mode: &str) {
+        let actions = self.take();
+
+        println!("Dry run report ({} mode): {} cross-seeds would have been added", mode, actions.len());
+        for action in actions {
+            println!("  {} ({} from {})", action.candidate_name, action.name, action.indexer);
+            println!("    would {}", action.action);
+        }
+    }
+}
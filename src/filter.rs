@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+
+use crate::config::{ContentFilter, FilterConfig};
+use crate::library::TorrentMetadata;
+use crate::torrent_client::TorrentInfo;
+
+/// The rule a torrent was excluded by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FilterRule {
+    Category,
+    Tag,
+    Tracker,
+    Name,
+    Size,
+    FileCount,
+    Age,
+    Content,
+}
+
+impl FilterRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterRule::Category => "category",
+            FilterRule::Tag => "tag",
+            FilterRule::Tracker => "tracker",
+            FilterRule::Name => "name",
+            FilterRule::Size => "size",
+            FilterRule::FileCount => "file count",
+            FilterRule::Age => "age",
+            FilterRule::Content => "content",
+        }
+    }
+}
+
+/// How many torrents each rule excluded.
+#[derive(Debug, Default)]
+pub struct FilterSummary {
+    pub excluded: HashMap<FilterRule, usize>,
+}
+
+impl FilterSummary {
+    pub fn record(&mut self, rule: FilterRule) {
+        *self.excluded.entry(rule).or_default() += 1;
+    }
+
+    pub fn total(&self) -> usize {
+        self.excluded.values().sum()
+    }
+}
+
+impl fmt::Display for FilterSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rules: Vec<_> = self.excluded.iter().collect();
+        rules.sort();
+
+        let rules: Vec<String> = rules.into_iter()
+            .map(|(rule, count)| format!("{}: {}", rule.as_str(), count))
+            .collect();
+
+        write!(f, "{}", rules.join(", "))
+    }
+}
+
+/// The filter rules of the config, with the regexes compiled.
+pub struct TorrentFilter {
+    config: FilterConfig,
+    include_name: Option<Regex>,
+    exclude_name: Option<Regex>,
+    episode: Regex,
+    season: Regex,
+}
+
+impl TorrentFilter {
+    pub fn new(config: &FilterConfig) -> Result<Self, regex::Error> {
+        Ok(Self {
+            config: config.clone(),
+            include_name: config.include_name.as_deref().map(Regex::new).transpose()?,
+            exclude_name: config.exclude_name.as_deref().map(Regex::new).transpose()?,
+            episode: Regex::new(r"(?i)\bS\d{1,3}E\d{1,4}\b|\b\d{1,2}x\d{2,3}\b")?,
+            season: Regex::new(r"(?i)\bS\d{1,3}\b|\bSeason[ ._-]?\d{1,3}\b")?,
+        })
+    }
+
+    /// Check a torrent against the rules, returning the first rule it is excluded by.
+    ///
+    /// Torrents without a known time they were added pass the age rules.
+    pub fn check(&self, torrent: &TorrentMetadata, info: &TorrentInfo) -> Result<(), FilterRule> {
+        let config = &self.config;
+
+        if !passes_list(&[info.category.clone()], &config.include_categories, &config.exclude_categories) {
+            return Err(FilterRule::Category);
+        }
+
+        if !passes_list(&info.tags, &config.include_tags, &config.exclude_tags) {
+            return Err(FilterRule::Tag);
+        }
+
+        if !self.passes_trackers(torrent) {
+            return Err(FilterRule::Tracker);
+        }
+
+        let name_included = self.include_name.as_ref().map_or(true, |regex| regex.is_match(&torrent.name));
+        let name_excluded = self.exclude_name.as_ref().map_or(false, |regex| regex.is_match(&torrent.name));
+        if !name_included || name_excluded {
+            return Err(FilterRule::Name);
+        }
+
+        let size = torrent.length;
+        if config.min_size.map_or(false, |min| size < min) || config.max_size.map_or(false, |max| size > max) {
+            return Err(FilterRule::Size);
+        }
+
+        let files = torrent.files.len();
+        if config.min_files.map_or(false, |min| files < min) || config.max_files.map_or(false, |max| files > max) {
+            return Err(FilterRule::FileCount);
+        }
+
+        if let Some(age) = added_time(torrent, info).and_then(|added| added.elapsed().ok()) {
+            if config.min_age.map_or(false, |min| age < min) || config.max_age.map_or(false, |max| age > max) {
+                return Err(FilterRule::Age);
+            }
+        }
+
+        if !self.passes_content(&torrent.name) {
+            return Err(FilterRule::Content);
+        }
+
+        Ok(())
+    }
+
+    fn passes_trackers(&self, torrent: &TorrentMetadata) -> bool {
+        let config = &self.config;
+        if config.include_trackers.is_empty() && config.exclude_trackers.is_empty() {
+            return true;
+        }
+
+        let hosts: Vec<String> = announce_hosts(torrent);
+        let matches = |filter: &Vec<String>| hosts.iter()
+            .any(|host| filter.iter().any(|entry| host_matches(host, entry)));
+
+        !matches(&config.exclude_trackers)
+            && (config.include_trackers.is_empty() || matches(&config.include_trackers))
+    }
+
+    fn passes_content(&self, name: &str) -> bool {
+        let is_episode = self.episode.is_match(name);
+
+        match self.config.content {
+            ContentFilter::All => true,
+            ContentFilter::Episodes => is_episode,
+            ContentFilter::SeasonPacks => !is_episode && self.season.is_match(name),
+        }
+    }
+}
+
+/// Check if any of the values pass an include and exclude list. The lists are case insensitive,
+/// and an empty include list includes everything.
+fn passes_list(values: &[String], include: &[String], exclude: &[String]) -> bool {
+    let matches = |list: &[String]| values.iter()
+        .any(|value| list.iter().any(|item| item.eq_ignore_ascii_case(value)));
+
+    !matches(exclude) && (include.is_empty() || matches(include))
+}
+
+/// Check if a host is the same as, or a subdomain of, another host.
+fn host_matches(host: &str, other: &str) -> bool {
+    let host = host.to_lowercase();
+    let other = other.trim().trim_start_matches('.').to_lowercase();
+
+    host == other || host.ends_with(&format!(".{}", other))
+}
+
+/// Get the hosts of all announce urls of a torrent.
+fn announce_hosts(torrent: &TorrentMetadata) -> Vec<String> {
+    torrent.announce_list.iter()
+        .flatten()
+        .filter_map(|url| reqwest::Url::parse(url).ok())
+        .filter_map(|url| url.host_str().map(|host| host.to_string()))
+        .collect()
+}
+
+/// Get when a torrent was added to its client. Clients that don't report it fall back to the modification
+/// time of the `.torrent` file.
+fn added_time(torrent: &TorrentMetadata, info: &TorrentInfo) -> Option<SystemTime> {
+    match info.added_on {
+        Some(added) => Some(UNIX_EPOCH + Duration::from_secs(added.max(0) as u64)),
+        None => torrent.path.as_ref()
+            .and_then(|path| std::fs::metadata(path).and_then(|meta| meta.modified()).ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn torrent() -> TorrentMetadata {
+        TorrentMetadata {
+            path: None,
+            name: String::from("Show.S01.1080p"),
+            info_hash: String::from("abc"),
+            files: vec![],
+            length: 1,
+            private: false,
+            announce_list: vec![],
+        }
+    }
+
+    fn added_days_ago(days: i64) -> TorrentInfo {
+        TorrentInfo {
+            added_on: Some(crate::util::unix_timestamp() - days * DAY.as_secs() as i64),
+            ..TorrentInfo::from_hash(String::from("abc"))
+        }
+    }
+
+    #[test]
+    fn age_is_when_the_client_added_the_torrent() {
+        let filter = TorrentFilter::new(&FilterConfig {
+            min_age: Some(DAY),
+            max_age: Some(7 * DAY),
+            ..FilterConfig::default()
+        }).unwrap();
+
+        assert_eq!(filter.check(&torrent(), &added_days_ago(0)), Err(FilterRule::Age));
+        assert_eq!(filter.check(&torrent(), &added_days_ago(3)), Ok(()));
+        assert_eq!(filter.check(&torrent(), &added_days_ago(10)), Err(FilterRule::Age));
+        // Without a time from the client or a `.torrent` file, the age is unknown.
+        assert_eq!(filter.check(&torrent(), &TorrentInfo::from_hash(String::from("abc"))), Ok(()));
+    }
+}
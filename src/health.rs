@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// The amount of failed requests in a row after which an indexer or a torrent client is considered failing.
+pub const FAILING_THRESHOLD: u32 = 3;
+
+/// The health of an indexer or a torrent client, based on the outcome of the requests made to it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Health {
+    /// Unix timestamp of the last successful request.
+    pub last_success: Option<i64>,
+    /// Unix timestamp of the last failed request.
+    pub last_failure: Option<i64>,
+    pub last_error: Option<String>,
+    /// The amount of requests that failed since the last successful one.
+    pub consecutive_failures: u32,
+    pub requests: u64,
+    pub failures: u64,
+}
+
+impl Health {
+    /// Healthy if the last request didn't fail.
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures == 0
+    }
+
+    fn record_success(&mut self) {
+        self.requests += 1;
+        self.consecutive_failures = 0;
+        self.last_success = Some(crate::util::unix_timestamp());
+    }
+
+    /// Returns true if this failure made it reach the failing threshold.
+    fn record_failure(&mut self, error: String) -> bool {
+        self.requests += 1;
+        self.failures += 1;
+        self.consecutive_failures += 1;
+        self.last_failure = Some(crate::util::unix_timestamp());
+        self.last_error = Some(error);
+
+        self.consecutive_failures == FAILING_THRESHOLD
+    }
+}
+
+/// Keeps track of the health of every indexer and torrent client.
+#[derive(Debug, Default)]
+pub struct HealthTracker {
+    indexers: Mutex<HashMap<String, Health>>,
+    /// Health of the torrent clients, keyed by their name.
+    clients: Mutex<HashMap<String, Health>>,
+}
+
+impl HealthTracker {
+    /// Record a successful request to an indexer.
+    pub fn record_success(&self, indexer: &str) {
+        self.indexers.lock().unwrap()
+            .entry(indexer.to_string())
+            .or_default()
+            .record_success();
+    }
+
+    /// Record a failed request to an indexer. Returns true if the indexer just started failing.
+    pub fn record_failure(&self, indexer: &str, error: String) -> bool {
+        self.indexers.lock().unwrap()
+            .entry(indexer.to_string())
+            .or_default()
+            .record_failure(error)
+    }
+
+    /// Record a successful request to a torrent client.
+    pub fn record_client_success(&self, client: &str) {
+        self.clients.lock().unwrap()
+            .entry(client.to_string())
+            .or_default()
+            .record_success();
+    }
+
+    /// Record a failed request to a torrent client. Returns true if the client just started failing.
+    pub fn record_client_failure(&self, client: &str, error: String) -> bool {
+        self.clients.lock().unwrap()
+            .entry(client.to_string())
+            .or_default()
+            .record_failure(error)
+    }
+
+    /// Get the health of a torrent client. Clients that weren't used yet have a default health.
+    pub fn client(&self, client: &str) -> Health {
+        self.clients.lock().unwrap()
+            .get(client)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the health of an indexer. Indexers that weren't used yet have a default health.
+    pub fn get(&self, indexer: &str) -> Health {
+        self.indexers.lock().unwrap()
+            .get(indexer)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clients_are_tracked_separately() {
+        let health = HealthTracker::default();
+
+        for _ in 1..FAILING_THRESHOLD {
+            assert!(!health.record_client_failure("qbittorrent", String::from("refused")));
+        }
+        health.record_client_success("deluge");
+        assert!(health.record_client_failure("qbittorrent", String::from("refused")));
+
+        assert!(!health.client("qbittorrent").is_healthy());
+        assert_eq!(health.client("qbittorrent").last_error.as_deref(), Some("refused"));
+        assert!(health.client("deluge").is_healthy());
+        assert_eq!(health.client("deluge").requests, 1);
+        assert_eq!(health.client("transmission").requests, 0);
+
+        health.record_client_success("qbittorrent");
+        assert!(health.client("qbittorrent").is_healthy());
+    }
+}
@@ -4,6 +4,8 @@ use lava_torrent::torrent::v1::Torrent;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 
+use crate::config::ProxyConfig;
+use crate::library::TorrentMetadata;
 use crate::torznab::{TorznabClient, GenericSearchParameters, SearchFunction};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -17,36 +19,54 @@ pub struct Indexer {
     pub url: String,
     /// API key to pass to prowlarr/jackett
     pub api_key: String,
+    /// Proxy to use for this indexer, overrides the global proxy.
+    pub proxy: Option<ProxyConfig>,
 
     #[serde(skip)]
     pub client: Option<Arc<RwLock<TorznabClient>>>, // TODO: Create a client pool.
 }
 
 impl Indexer {
-    pub async fn create_client(&mut self) -> Result<&Arc<RwLock<TorznabClient>>, crate::torznab::ClientError> {
+    /// Create the torznab client of the indexer. The indexer's own proxy is used if it has one,
+    /// else it falls back to `global_proxy`.
+    pub async fn create_client(&mut self, global_proxy: Option<&ProxyConfig>, no_proxy: &[String]) -> Result<&Arc<RwLock<TorznabClient>>, crate::torznab::ClientError> {
         if self.client.is_none() {
-            self.client = Some(Arc::new(RwLock::new(TorznabClient::new(self.name.clone(), &self.url, &self.api_key).await?)));
+            let proxy = self.proxy.as_ref().or(global_proxy);
+            let http = crate::util::http_client(proxy, no_proxy)?;
+
+            self.client = Some(Arc::new(RwLock::new(TorznabClient::new(self.name.clone(), &self.url, &self.api_key, http).await?)));
         }
 
         Ok(self.client.as_ref().unwrap())
     }
 
     /// Search an indexer for a torrent with its name, and return the found torrent.
-    pub async fn search_indexer(&self, torrent: &Torrent) -> Result<Option<Torrent>, crate::torznab::ClientError> {
+    pub async fn search_indexer(&self, torrent: &TorrentMetadata) -> Result<Option<Torrent>, crate::torznab::ClientError> {
         // The client should be set to something already
         let client = self.client.as_ref().unwrap().read().await;
 
         let generic = GenericSearchParameters::builder()
             .query(torrent.name.clone())
             .build();
-        let results = client.search(SearchFunction::Search, generic).await.unwrap();
+        let results = client.search(SearchFunction::Search, generic).await?;
+
+        // Clone the http client so the torrent is downloaded through the indexer's proxy.
+        let http = client.http().clone();
         
         // Drop the indexer client asap for other torrent searches.
         drop(client);
 
         // The first result should be the correct one.
         if let Some(result) = results.first() {
-            let found_torrent = result.download_torrent().await?;
+            let metrics = &crate::metrics::METRICS;
+            let found_torrent = match result.download_torrent(&http).await {
+                Ok(torrent) => torrent,
+                Err(err) => {
+                    metrics.indexer_errors.with_label_values(&[&self.name]).inc();
+                    return Err(err);
+                }
+            };
+            metrics.indexer_downloads.with_label_values(&[&self.name]).inc();
 
             Ok(Some(found_torrent)) 
         } else {
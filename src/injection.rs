@@ -0,0 +1,394 @@
+use std::path::Path;
+
+use lava_torrent::torrent::v1::Torrent;
+use tokio::time::{sleep, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::{IncompletePolicy, InjectionConfig};
+use crate::library::TorrentMetadata;
+use crate::torrent_client::{ClientResult, ContentLayout, TorrentClient, TorrentInfo, TorrentState};
+
+/// The outcome of adding a cross-seed to the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InjectionOutcome {
+    /// The cross-seed was added and started without a recheck, or only its trackers were added.
+    Added,
+    /// The cross-seed was rechecked, found complete and resumed.
+    Verified,
+    /// The cross-seed wasn't complete after its recheck, so it was handled with the policy.
+    Incomplete {
+        /// The progress of the cross-seed after its recheck, from `0.0` to `1.0`.
+        progress: f64,
+        policy: IncompletePolicy,
+    },
+    /// The recheck didn't finish in time, the cross-seed was left paused.
+    TimedOut,
+}
+
+impl InjectionOutcome {
+    /// Whether the cross-seed is seeding, or will be once the client gets to it.
+    pub fn is_seeding(&self) -> bool {
+        matches!(self, InjectionOutcome::Added | InjectionOutcome::Verified)
+    }
+
+    /// Describe the outcome for the decision log, `None` if the cross-seed is seeding.
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            InjectionOutcome::Added | InjectionOutcome::Verified => None,
+            InjectionOutcome::Incomplete { progress, policy } => {
+                Some(format!("{} at {:.1}% after recheck", policy.as_str(), progress * 100.0))
+            },
+            InjectionOutcome::TimedOut => Some(String::from("recheck timed out, left paused")),
+        }
+    }
+}
+
+/// The name of the local torrent's content in its client. The folder of a multi file torrent may have been
+/// renamed in the client, which reports the torrent under the new name.
+pub fn content_name<'a>(torrent: &'a TorrentMetadata, info: &'a TorrentInfo) -> &'a str {
+    match torrent.is_multi_file() && !info.name.is_empty() {
+        true => &info.name,
+        false => &torrent.name,
+    }
+}
+
+/// Where to add a cross-seed so its files are found where the files of the local torrent `info` are, given the
+/// save path of the local torrent. Returns the save path of the cross-seed and how to lay out its content.
+pub fn place_content(torrent: &TorrentMetadata, info: &TorrentInfo, found_torrent: &Torrent, save_path: &str) -> (String, ContentLayout) {
+    let save_path = save_path.to_string();
+    let name = content_name(torrent, info);
+
+    match (torrent.is_multi_file(), found_torrent.files.is_some()) {
+        (true, true) if name == found_torrent.name => (save_path, ContentLayout::Original),
+        (true, true) => (save_path, ContentLayout::Renamed(name.to_string())),
+        // The single file of the cross-seed is in the folder of the local torrent.
+        (true, false) => {
+            let folder = Path::new(&save_path).join(name);
+            (folder.to_string_lossy().to_string(), ContentLayout::Original)
+        },
+        (false, true) => (save_path, ContentLayout::NoSubfolder),
+        (false, false) => (save_path, ContentLayout::Original),
+    }
+}
+
+/// How the content of a local torrent is laid out in its client, so it can be added again the same way. A
+/// multi file torrent whose name differs in the client has had its folder renamed.
+pub fn local_layout(torrent: &TorrentMetadata, info: &TorrentInfo) -> ContentLayout {
+    match content_name(torrent, info) {
+        name if name != torrent.name => ContentLayout::Renamed(name.to_string()),
+        _ => ContentLayout::Original,
+    }
+}
+
+/// Poll the client until it lists a torrent that was just added. Returns `false` if it didn't show up
+/// before the timeout.
+pub async fn wait_for_torrent(client: &TorrentClient, hash: &str, config: &InjectionConfig) -> ClientResult<bool> {
+    let deadline = Instant::now() + config.check_timeout();
+
+    while Instant::now() < deadline {
+        if client.fetch_torrent_info(hash).await?.is_some() {
+            return Ok(true);
+        }
+
+        sleep(config.poll_interval()).await;
+    }
+
+    Ok(false)
+}
+
+/// Whether the client is still working on the torrent's data. Torrents queued for a check or
+/// checking their resume data are reported as checking by the clients.
+fn is_checking(state: &TorrentState) -> bool {
+    matches!(state, TorrentState::CheckingUploading | TorrentState::CheckingDownloading
+        | TorrentState::Allocating | TorrentState::Moving)
+}
+
+/// Recheck a cross-seed that was added paused, and resume it only if its data is complete. An
+/// incomplete cross-seed is handled with `policy`.
+pub async fn verify_injection(client: &TorrentClient, hash: &str, config: &InjectionConfig, policy: IncompletePolicy) -> ClientResult<InjectionOutcome> {
+    let torrent = TorrentInfo::from_hash(hash.to_string());
+
+    let before = check_status(client, hash).await?;
+
+    debug!("Rechecking cross-seed {} in {}...", hash, client.name());
+    client.recheck_torrent(&torrent).await?;
+
+    if !wait_for_check(client, hash, before, config).await? {
+        warn!("Recheck of cross-seed {} in {} didn't finish in time, leaving it paused", hash, client.name());
+        return Ok(InjectionOutcome::TimedOut);
+    }
+
+    let progress = client.get_torrent_progress(hash).await?.unwrap_or_default();
+    if progress >= 1.0 {
+        client.resume_torrent(&torrent).await?;
+        info!("Verified cross-seed {} in {}, resumed it", hash, client.name());
+
+        return Ok(InjectionOutcome::Verified);
+    }
+
+    warn!("Cross-seed {} in {} is only {:.1}% complete after its recheck, {}",
+        hash, client.name(), progress * 100.0, policy.as_str());
+
+    match policy {
+        IncompletePolicy::Pause => {},
+        IncompletePolicy::Tag => client.add_torrent_tags(&torrent, vec![config.review_tag()]).await?,
+        IncompletePolicy::Remove => client.remove_torrent(&torrent, false).await?,
+    }
+
+    Ok(InjectionOutcome::Incomplete { progress, policy })
+}
+
+/// The state and progress of a torrent, `None` if the client doesn't have it.
+async fn check_status(client: &TorrentClient, hash: &str) -> ClientResult<(Option<TorrentState>, Option<f64>)> {
+    let state = client.fetch_torrent_info(hash).await?.map(|info| info.state);
+    let progress = client.get_torrent_progress(hash).await?;
+
+    Ok((state, progress))
+}
+
+/// Poll the torrent until its recheck is done. The check is done once it was seen running, or once the
+/// torrent isn't checking and its state or progress moved away from `before`, the status before the recheck,
+/// since small torrents can be checked between two polls. Returns `false` if it didn't finish before the timeout.
+async fn wait_for_check(client: &TorrentClient, hash: &str, before: (Option<TorrentState>, Option<f64>), config: &InjectionConfig) -> ClientResult<bool> {
+    let deadline = Instant::now() + config.check_timeout();
+    let mut started = false;
+
+    while Instant::now() < deadline {
+        sleep(config.poll_interval()).await;
+
+        let status = check_status(client, hash).await?;
+        if status.0.as_ref().map_or(false, is_checking) {
+            started = true;
+        } else if started || status != before {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use crate::library::LibraryFile;
+    use crate::torrent_client::{TorrentBackend, TorrentFile, TorrentUpload};
+
+    use super::*;
+
+    /// A client whose recheck is done before the first poll. Until the recheck, the torrent's resume data is
+    /// being checked, like right after it was added.
+    struct CheckedBackend {
+        torrent: Mutex<(TorrentState, f64)>,
+        checked_progress: f64,
+    }
+
+    impl CheckedBackend {
+        fn client(checked_progress: f64) -> TorrentClient {
+            let backend = CheckedBackend {
+                torrent: Mutex::new((TorrentState::CheckingDownloading, 0.0)),
+                checked_progress,
+            };
+
+            TorrentClient::new(String::from("stand-in"), Box::new(backend))
+        }
+    }
+
+    #[async_trait]
+    impl TorrentBackend for CheckedBackend {
+        async fn login(&mut self) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+            Ok(vec![])
+        }
+
+        async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+            let (state, _) = *self.torrent.lock().unwrap();
+            Ok(Some(TorrentInfo { state, ..TorrentInfo::from_hash(hash.to_string()) }))
+        }
+
+        async fn get_torrent_trackers(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+            Ok(vec![])
+        }
+
+        async fn get_torrent_files(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+            Ok(vec![])
+        }
+
+        async fn get_torrent_progress(&self, _hash: &str) -> ClientResult<Option<f64>> {
+            Ok(Some(self.torrent.lock().unwrap().1))
+        }
+
+        async fn add_torrent_trackers(&self, _torrent: &TorrentInfo, _trackers: Vec<String>) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn recheck_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            let state = match self.checked_progress >= 1.0 {
+                true => TorrentState::PausedUploading,
+                false => TorrentState::PausedDownloading,
+            };
+            *self.torrent.lock().unwrap() = (state, self.checked_progress);
+
+            Ok(())
+        }
+
+        async fn resume_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            self.torrent.lock().unwrap().0 = TorrentState::Uploading;
+            Ok(())
+        }
+
+        async fn add_torrent(&self, _upload: &TorrentUpload) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn remove_torrent(&self, _torrent: &TorrentInfo, _delete_files: bool) -> ClientResult<()> {
+            Ok(())
+        }
+    }
+
+    fn injection_config() -> InjectionConfig {
+        InjectionConfig {
+            poll_interval: Some(Duration::from_millis(10)),
+            check_timeout: Some(Duration::from_secs(5)),
+            ..InjectionConfig::default()
+        }
+    }
+
+    fn local_torrent(name: &str, files: &[&str]) -> TorrentMetadata {
+        TorrentMetadata {
+            path: None,
+            name: name.to_string(),
+            info_hash: String::from("abc"),
+            files: files.iter().map(|path| LibraryFile { path: PathBuf::from(path), length: 10 }).collect(),
+            length: 10 * files.len() as u64,
+            private: false,
+            announce_list: vec![],
+        }
+    }
+
+    /// Bencode a found torrent, with `files` a multi file torrent and without it a single file torrent.
+    fn found_torrent(name: &str, files: Option<&[&str]>) -> Torrent {
+        let content = match files {
+            Some(files) => {
+                let files: String = files.iter()
+                    .map(|path| format!("d6:lengthi10e4:pathl{}:{}ee", path.len(), path))
+                    .collect();
+                format!("5:filesl{}e", files)
+            },
+            None => String::from("6:lengthi10e"),
+        };
+        let bytes = format!("d4:infod{}4:name{}:{}12:piece lengthi16384e6:pieces20:{}ee",
+            content, name.len(), name, "a".repeat(20));
+
+        Torrent::read_from_bytes(bytes.as_bytes()).unwrap()
+    }
+
+    /// The local torrent as its client reports it, named `name`.
+    fn client_info(name: &str) -> TorrentInfo {
+        TorrentInfo { name: name.to_string(), ..TorrentInfo::from_hash(String::from("abc")) }
+    }
+
+    #[test]
+    fn place_multi_file_in_same_folder() {
+        let torrent = local_torrent("Show.S01", &["E01.mkv", "E02.mkv"]);
+        let found = found_torrent("Show.S01", Some(&["E01.mkv", "E02.mkv"]));
+
+        assert_eq!(place_content(&torrent, &client_info(&torrent.name), &found, "/downloads"),
+            (String::from("/downloads"), ContentLayout::Original));
+    }
+
+    #[test]
+    fn place_multi_file_in_renamed_folder() {
+        let torrent = local_torrent("Show.S01", &["E01.mkv", "E02.mkv"]);
+        let found = found_torrent("Show.S01.REPACK", Some(&["E01.mkv", "E02.mkv"]));
+
+        assert_eq!(place_content(&torrent, &client_info(&torrent.name), &found, "/downloads"),
+            (String::from("/downloads"), ContentLayout::Renamed(String::from("Show.S01"))));
+    }
+
+    #[test]
+    fn place_multi_file_in_folder_renamed_in_client() {
+        let torrent = local_torrent("Show.S01", &["E01.mkv", "E02.mkv"]);
+        let found = found_torrent("Show.S01", Some(&["E01.mkv", "E02.mkv"]));
+
+        assert_eq!(place_content(&torrent, &client_info("Show S01"), &found, "/downloads"),
+            (String::from("/downloads"), ContentLayout::Renamed(String::from("Show S01"))));
+
+        let single = found_torrent("E01.mkv", None);
+        let folder = Path::new("/downloads").join("Show S01").to_string_lossy().to_string();
+        assert_eq!(place_content(&torrent, &client_info("Show S01"), &single, "/downloads"), (folder, ContentLayout::Original));
+    }
+
+    #[test]
+    fn place_single_file_in_local_folder() {
+        let torrent = local_torrent("Movie", &["Movie.mkv"]);
+        let found = found_torrent("Movie.mkv", None);
+
+        let folder = Path::new("/downloads").join("Movie").to_string_lossy().to_string();
+        assert_eq!(place_content(&torrent, &client_info(&torrent.name), &found, "/downloads"), (folder, ContentLayout::Original));
+    }
+
+    #[test]
+    fn place_multi_file_without_subfolder() {
+        let torrent = local_torrent("Movie.mkv", &["Movie.mkv"]);
+        let found = found_torrent("Movie", Some(&["Movie.mkv"]));
+
+        assert_eq!(place_content(&torrent, &client_info(&torrent.name), &found, "/downloads"),
+            (String::from("/downloads"), ContentLayout::NoSubfolder));
+    }
+
+    #[test]
+    fn place_single_file_next_to_local_file() {
+        let torrent = local_torrent("Movie.mkv", &["Movie.mkv"]);
+        let found = found_torrent("Movie.mkv", None);
+
+        assert_eq!(place_content(&torrent, &client_info(&torrent.name), &found, "/downloads"),
+            (String::from("/downloads"), ContentLayout::Original));
+    }
+
+    #[test]
+    fn layout_of_renamed_local_torrent() {
+        let torrent = local_torrent("Show.S01", &["E01.mkv", "E02.mkv"]);
+        let renamed = client_info("Show S01");
+        let original = client_info("Show.S01");
+
+        assert_eq!(local_layout(&torrent, &renamed), ContentLayout::Renamed(String::from("Show S01")));
+        assert_eq!(local_layout(&torrent, &original), ContentLayout::Original);
+        assert_eq!(local_layout(&local_torrent("Movie.mkv", &["Movie.mkv"]), &renamed), ContentLayout::Original);
+    }
+
+    #[tokio::test]
+    async fn bad_match_checked_before_first_poll_is_incomplete() {
+        let client = CheckedBackend::client(0.0);
+
+        let outcome = verify_injection(&client, "abc", &injection_config(), IncompletePolicy::Pause).await.unwrap();
+        assert_eq!(outcome, InjectionOutcome::Incomplete { progress: 0.0, policy: IncompletePolicy::Pause });
+    }
+
+    #[tokio::test]
+    async fn complete_match_checked_before_first_poll_is_verified() {
+        let client = CheckedBackend::client(1.0);
+
+        let outcome = verify_injection(&client, "abc", &injection_config(), IncompletePolicy::Pause).await.unwrap();
+        assert_eq!(outcome, InjectionOutcome::Verified);
+        assert_eq!(client.fetch_torrent_info("abc").await.unwrap().unwrap().state, TorrentState::Uploading);
+    }
+
+    #[test]
+    fn checking_states() {
+        assert!(is_checking(&TorrentState::CheckingDownloading));
+        assert!(is_checking(&TorrentState::CheckingUploading));
+        assert!(is_checking(&TorrentState::Allocating));
+        assert!(is_checking(&TorrentState::Moving));
+        assert!(!is_checking(&TorrentState::PausedDownloading));
+        assert!(!is_checking(&TorrentState::PausedUploading));
+        assert!(!is_checking(&TorrentState::Unknown));
+    }
+}
@@ -0,0 +1,587 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use lava_torrent::torrent::v1::Torrent;
+use serde::{Deserialize, Serialize};
+
+use crate::torrent_client::{TorrentFile, TorrentInfo, TorrentState};
+
+/// Normalise a torrent or release name so names from different sources can be compared.
+///
+/// The name is lowercased, the file extension is removed, and any separators
+/// (dots, underscores, dashes, spaces, brackets) are collapsed into a single space.
+pub fn normalise_name(name: &str) -> String {
+    let name = name.trim();
+
+    // Strip common file extensions from single file torrents.
+    let name = match name.rsplit_once('.') {
+        Some((stem, ext)) if matches!(ext.to_lowercase().as_str(), "mkv" | "mp4" | "avi" | "m4v" | "ts") => stem,
+        _ => name,
+    };
+
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Get the files of a torrent with their sizes, sorted by path.
+///
+/// Single file torrents don't have a file list, so the name of the torrent is used as the path.
+pub fn torrent_files(torrent: &Torrent) -> Vec<LibraryFile> {
+    let mut files: Vec<LibraryFile> = match &torrent.files {
+        Some(files) => files.iter()
+            .map(|file| LibraryFile { path: file.path.clone(), length: file.length as u64 })
+            .collect(),
+        None => vec![LibraryFile { path: PathBuf::from(&torrent.name), length: torrent.length as u64 }],
+    };
+    files.sort();
+
+    files
+}
+
+/// Counts the saves of the index, so concurrent saves write to their own temporary file.
+static SAVE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// The modification time of a file in nanoseconds since the unix epoch.
+fn modified_time(path: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+/// The parts of a local torrent file that are needed to search for it and match releases against it.
+///
+/// This is much smaller than a `Torrent` since the piece hashes aren't kept. The full torrent is only
+/// reloaded from the file when it has to be encoded again.
+#[derive(Debug, Clone)]
+pub struct TorrentMetadata {
+    /// Path of the `.torrent` file. Torrents read from the metadata of a client that can't export
+    /// torrents don't have one.
+    pub path: Option<PathBuf>,
+    pub name: String,
+    pub info_hash: String,
+    pub files: Vec<LibraryFile>,
+    pub length: u64,
+    pub private: bool,
+    /// The announce urls in tiers. A torrent without an announce list has its announce url as the only tier.
+    pub announce_list: Vec<Vec<String>>,
+}
+
+impl TorrentMetadata {
+    pub fn new(path: PathBuf, torrent: &Torrent) -> Self {
+        let announce_list = match (&torrent.announce_list, &torrent.announce) {
+            (Some(list), _) => list.clone(),
+            (None, Some(announce)) => vec![vec![announce.clone()]],
+            (None, None) => vec![],
+        };
+
+        Self {
+            path: Some(path),
+            name: torrent.name.clone(),
+            info_hash: torrent.info_hash(),
+            files: torrent_files(torrent),
+            length: torrent.length as u64,
+            private: torrent.is_private(),
+            announce_list,
+        }
+    }
+
+    /// Create the metadata of a torrent from what its client reports about it, for clients that can't export
+    /// the `.torrent` file.
+    pub fn from_client(info: &TorrentInfo, files: Vec<TorrentFile>, trackers: Vec<String>) -> Self {
+        let mut files: Vec<LibraryFile> = files.into_iter()
+            .map(|file| LibraryFile { path: file.path, length: file.length })
+            .collect();
+        files.sort();
+
+        Self {
+            path: None,
+            name: info.name.clone(),
+            info_hash: info.hash.to_lowercase(),
+            length: files.iter().map(|file| file.length).sum(),
+            files,
+            // Clients don't report the private flag, it's only known from the `.torrent` file.
+            private: false,
+            announce_list: trackers.into_iter().map(|tracker| vec![tracker]).collect(),
+        }
+    }
+
+    /// Read the metadata of a `.torrent` file. The full torrent is dropped after reading it.
+    pub fn read(path: PathBuf) -> Result<Self, lava_torrent::LavaTorrentError> {
+        let torrent = Torrent::read_from_file(&path)?;
+        Ok(Self::new(path, &torrent))
+    }
+
+    /// Whether the torrent's files are in a folder. A single file torrent's only file is named after the torrent.
+    pub fn is_multi_file(&self) -> bool {
+        !matches!(self.files.as_slice(), [file] if file.path == Path::new(&self.name))
+    }
+
+    /// Reload the full torrent from its `.torrent` file.
+    pub fn load_torrent(&self) -> Result<Torrent, lava_torrent::LavaTorrentError> {
+        match &self.path {
+            Some(path) => Torrent::read_from_file(path),
+            None => Err(std::io::Error::new(std::io::ErrorKind::NotFound,
+                format!("{} has no .torrent file, its client can't export torrents", self.name)).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LibraryFile {
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+/// The state of a library torrent in the torrent client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClientState {
+    /// The torrent is done downloading and is seeding.
+    Complete,
+    /// The torrent is in the client, but isn't done downloading.
+    Incomplete,
+    /// The torrent wasn't found in the client.
+    Missing,
+}
+
+impl ClientState {
+    pub fn from_info(info: Option<&TorrentInfo>) -> Self {
+        match info.map(|info| &info.state) {
+            Some(TorrentState::Uploading | TorrentState::QueuedUploading) => ClientState::Complete,
+            Some(_) => ClientState::Incomplete,
+            None => ClientState::Missing,
+        }
+    }
+}
+
+/// A torrent in the local library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryEntry {
+    pub name: String,
+    pub normalised_name: String,
+    pub info_hash: String,
+    pub total_size: u64,
+    pub files: Vec<LibraryFile>,
+    /// Path of the `.torrent` file, used to load the full torrent when it's needed. Torrents from a
+    /// client that can't export torrents don't have one.
+    #[serde(default)]
+    pub torrent_path: Option<PathBuf>,
+    /// Modification time of the `.torrent` file when it was indexed, in nanoseconds since the unix epoch.
+    /// The file isn't parsed again while it's unchanged.
+    #[serde(default)]
+    pub torrent_modified: Option<u64>,
+    #[serde(default)]
+    pub private: bool,
+    #[serde(default)]
+    pub announce_list: Vec<Vec<String>>,
+    pub state: ClientState,
+}
+
+impl LibraryEntry {
+    pub fn new(torrent: &TorrentMetadata, info: Option<&TorrentInfo>) -> Self {
+        Self {
+            name: torrent.name.clone(),
+            normalised_name: normalise_name(&torrent.name),
+            info_hash: torrent.info_hash.clone(),
+            total_size: torrent.length,
+            files: torrent.files.clone(),
+            torrent_path: torrent.path.clone(),
+            torrent_modified: torrent.path.as_deref().and_then(modified_time),
+            private: torrent.private,
+            announce_list: torrent.announce_list.clone(),
+            state: ClientState::from_info(info),
+        }
+    }
+
+    /// Read the metadata of the torrent from its `.torrent` file, or use the entry itself if it has no file.
+    pub fn load_metadata(&self) -> Result<TorrentMetadata, lava_torrent::LavaTorrentError> {
+        match &self.torrent_path {
+            Some(path) => TorrentMetadata::read(path.clone()),
+            None => Ok(self.metadata()),
+        }
+    }
+
+    /// The metadata of the torrent as it was indexed.
+    fn metadata(&self) -> TorrentMetadata {
+        TorrentMetadata {
+            path: self.torrent_path.clone(),
+            name: self.name.clone(),
+            info_hash: self.info_hash.clone(),
+            files: self.files.clone(),
+            length: self.total_size,
+            private: self.private,
+            announce_list: self.announce_list.clone(),
+        }
+    }
+
+    /// Check if a torrent has the same files with the same sizes as this entry.
+    pub fn files_match(&self, torrent: &Torrent) -> bool {
+        self.total_size == torrent.length as u64 && self.files == torrent_files(torrent)
+    }
+}
+
+/// The format the library index is stored on disk with.
+#[derive(Serialize, Deserialize)]
+struct StoredLibrary {
+    entries: Vec<LibraryEntry>,
+}
+
+/// The changes made to the index by a sync.
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// An index of the torrents in the local library, used to check if a release
+/// from an indexer could be a cross-seed of a torrent we already have.
+#[derive(Debug, Default)]
+pub struct LibraryIndex {
+    /// The torrents in the library, keyed by their info hash.
+    entries: HashMap<String, LibraryEntry>,
+    /// Info hashes of torrents with the same normalised name.
+    by_name: HashMap<String, Vec<String>>,
+    /// Info hashes of torrents with the same total size.
+    by_size: HashMap<u64, Vec<String>>,
+}
+
+impl LibraryIndex {
+    /// Load the index from a file. An empty index is returned if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self, LibraryError> {
+        let mut index = LibraryIndex::default();
+
+        if path.exists() {
+            let file = std::fs::File::open(path)?;
+            let stored: StoredLibrary = serde_json::from_reader(std::io::BufReader::new(file))?;
+
+            for entry in stored.entries {
+                index.insert(entry);
+            }
+        }
+
+        Ok(index)
+    }
+
+    /// Save the index to a file. The index is written to a temporary file first so
+    /// an interrupted save doesn't corrupt it. The temporary file is unique to the save, since
+    /// a `search` command can save the index while the daemon is running.
+    pub fn save(&self, path: &Path) -> Result<(), LibraryError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let stored = StoredLibrary {
+            entries: self.entries.values().cloned().collect(),
+        };
+
+        let save = SAVE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), save));
+        let file = std::fs::File::create(&tmp_path)?;
+        serde_json::to_writer(std::io::BufWriter::new(file), &stored)?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Insert an entry into the index, replacing the entry with the same info hash.
+    pub fn insert(&mut self, entry: LibraryEntry) {
+        self.remove(&entry.info_hash);
+
+        self.by_name.entry(entry.normalised_name.clone())
+            .or_default()
+            .push(entry.info_hash.clone());
+        self.by_size.entry(entry.total_size)
+            .or_default()
+            .push(entry.info_hash.clone());
+        self.entries.insert(entry.info_hash.clone(), entry);
+    }
+
+    /// Remove an entry from the index.
+    pub fn remove(&mut self, info_hash: &str) -> Option<LibraryEntry> {
+        let entry = self.entries.remove(info_hash)?;
+
+        if let Some(hashes) = self.by_name.get_mut(&entry.normalised_name) {
+            hashes.retain(|hash| hash != info_hash);
+            if hashes.is_empty() {
+                self.by_name.remove(&entry.normalised_name);
+            }
+        }
+
+        if let Some(hashes) = self.by_size.get_mut(&entry.total_size) {
+            hashes.retain(|hash| hash != info_hash);
+            if hashes.is_empty() {
+                self.by_size.remove(&entry.total_size);
+            }
+        }
+
+        Some(entry)
+    }
+
+    /// Sync the index with the current entries of the library. Entries that are unchanged
+    /// are left alone, and entries of torrents that are no longer in the library are removed.
+    pub fn sync(&mut self, entries: Vec<LibraryEntry>) -> SyncSummary {
+        let mut summary = SyncSummary::default();
+        let current: HashSet<String> = entries.iter()
+            .map(|entry| entry.info_hash.clone())
+            .collect();
+
+        for entry in entries {
+            match self.entries.get(&entry.info_hash) {
+                Some(existing) if existing.state == entry.state && existing.torrent_path == entry.torrent_path
+                    && existing.torrent_modified == entry.torrent_modified => {},
+                Some(_) => {
+                    summary.updated += 1;
+                    self.insert(entry);
+                },
+                None => {
+                    summary.added += 1;
+                    self.insert(entry);
+                },
+            }
+        }
+
+        let removed: Vec<String> = self.entries.keys()
+            .filter(|hash| !current.contains(*hash))
+            .cloned()
+            .collect();
+        for hash in removed {
+            summary.removed += 1;
+            self.remove(&hash);
+        }
+
+        summary
+    }
+
+    /// The metadata of the indexed torrents whose `.torrent` file wasn't modified since it was indexed, keyed
+    /// by the path of the file. These files don't have to be parsed again.
+    pub fn unchanged_torrents(&self) -> HashMap<PathBuf, TorrentMetadata> {
+        self.entries.values()
+            .filter_map(|entry| {
+                let path = entry.torrent_path.as_ref()?;
+                match entry.torrent_modified {
+                    Some(modified) if modified_time(path) == Some(modified) => Some((path.clone(), entry.metadata())),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Get a torrent in the library by its info hash.
+    pub fn get(&self, info_hash: &str) -> Option<&LibraryEntry> {
+        self.entries.get(info_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Find torrents in the library with the same normalised name.
+    pub fn find_by_name(&self, name: &str) -> Vec<&LibraryEntry> {
+        self.lookup(self.by_name.get(&normalise_name(name)))
+    }
+
+    /// Find torrents in the library with the same total size.
+    pub fn find_by_size(&self, size: u64) -> Vec<&LibraryEntry> {
+        self.lookup(self.by_size.get(&size))
+    }
+
+    /// Find torrents in the library that could be the same content as a release.
+    ///
+    /// The normalised names must match, and when the size of the release is known
+    /// the total size of the torrent must match it exactly. If no name matches, torrents with
+    /// the same total size are returned instead, since releases are often renamed on other trackers.
+    /// Their files are compared once the release is downloaded.
+    pub fn find_matches(&self, name: &str, size: Option<u64>) -> Vec<&LibraryEntry> {
+        let by_name: Vec<&LibraryEntry> = self.find_by_name(name)
+            .into_iter()
+            .filter(|entry| size.map_or(true, |size| entry.total_size == size))
+            .collect();
+
+        match size {
+            Some(size) if by_name.is_empty() => self.find_by_size(size),
+            _ => by_name,
+        }
+    }
+
+    fn lookup(&self, hashes: Option<&Vec<String>>) -> Vec<&LibraryEntry> {
+        hashes.map(|hashes| hashes.iter()
+                .filter_map(|hash| self.entries.get(hash))
+                .collect())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub enum LibraryError {
+    IoError(std::io::Error),
+    SerdeError(serde_json::Error),
+}
+
+impl From<std::io::Error> for LibraryError {
+    fn from(e: std::io::Error) -> Self {
+        LibraryError::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for LibraryError {
+    fn from(e: serde_json::Error) -> Self {
+        LibraryError::SerdeError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: &str, path: &Path, modified: Option<u64>) -> LibraryEntry {
+        LibraryEntry {
+            name: String::from("Name"),
+            normalised_name: normalise_name("Name"),
+            info_hash: hash.to_string(),
+            total_size: 1,
+            files: vec![],
+            torrent_path: Some(path.to_path_buf()),
+            torrent_modified: modified,
+            private: true,
+            announce_list: vec![vec![String::from("https://tracker/announce")]],
+            state: ClientState::Complete,
+        }
+    }
+
+    #[test]
+    fn only_unmodified_torrents_are_reused() {
+        let dir = std::env::temp_dir().join(format!("crate-library-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (same, modified, missing) = (dir.join("same.torrent"), dir.join("modified.torrent"), dir.join("missing.torrent"));
+        std::fs::write(&same, b"d").unwrap();
+        std::fs::write(&modified, b"d").unwrap();
+
+        let mut index = LibraryIndex::default();
+        index.insert(entry("a", &same, modified_time(&same)));
+        index.insert(entry("b", &modified, modified_time(&modified).map(|time| time - 1)));
+        index.insert(entry("c", &missing, Some(1)));
+        // Entries indexed before modification times were stored are parsed again.
+        index.insert(entry("d", &same, None));
+
+        let unchanged = index.unchanged_torrents();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(unchanged.len(), 1);
+        let torrent = &unchanged[&same];
+        assert_eq!(torrent.info_hash, "a");
+        assert!(torrent.private);
+        assert_eq!(torrent.announce_list, vec![vec![String::from("https://tracker/announce")]]);
+    }
+
+    fn named(hash: &str, name: &str, size: u64) -> LibraryEntry {
+        LibraryEntry {
+            name: name.to_string(),
+            normalised_name: normalise_name(name),
+            info_hash: hash.to_string(),
+            total_size: size,
+            files: vec![LibraryFile { path: PathBuf::from(name), length: size }],
+            torrent_path: None,
+            torrent_modified: None,
+            private: false,
+            announce_list: vec![],
+            state: ClientState::Complete,
+        }
+    }
+
+    fn hashes(entries: Vec<&LibraryEntry>) -> Vec<&str> {
+        let mut hashes: Vec<&str> = entries.into_iter().map(|entry| entry.info_hash.as_str()).collect();
+        hashes.sort();
+        hashes
+    }
+
+    #[test]
+    fn normalise_name_collapses_separators_and_case() {
+        assert_eq!(normalise_name("Some.Show.S01E01.1080p.WEB-DL"), "some show s01e01 1080p web dl");
+        assert_eq!(normalise_name("  Some_Show - [S01E01] (1080p)  "), "some show s01e01 1080p");
+        assert_eq!(normalise_name("Some.Movie.2020.MKV"), "some movie 2020");
+        assert_eq!(normalise_name("Some.Movie.2020.mkv"), "some movie 2020");
+        // Only video extensions are stripped.
+        assert_eq!(normalise_name("Some.Album.flac"), "some album flac");
+    }
+
+    #[test]
+    fn matches_by_name_then_by_size() {
+        let mut index = LibraryIndex::default();
+        index.insert(named("a", "Some.Show.S01E01.mkv", 100));
+        index.insert(named("b", "Some Show S01E01", 200));
+        index.insert(named("c", "Other.Show.S01E01", 300));
+
+        assert_eq!(hashes(index.find_matches("some_show_s01e01", None)), vec!["a", "b"]);
+        assert_eq!(hashes(index.find_matches("some_show_s01e01", Some(200))), vec!["b"]);
+        // A renamed release is matched by its size alone.
+        assert_eq!(hashes(index.find_matches("Renamed.Release", Some(300))), vec!["c"]);
+        assert_eq!(hashes(index.find_matches("some_show_s01e01", Some(300))), vec!["c"]);
+        assert!(index.find_matches("Renamed.Release", None).is_empty());
+        assert!(index.find_matches("Renamed.Release", Some(400)).is_empty());
+    }
+
+    #[test]
+    fn sync_counts_changes_and_keeps_unchanged_torrents() {
+        let dir = std::env::temp_dir().join(format!("crate-library-sync-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (kept, changed, gone) = (dir.join("kept.torrent"), dir.join("changed.torrent"), dir.join("gone.torrent"));
+        for path in [&kept, &changed, &gone] {
+            std::fs::write(path, b"d").unwrap();
+        }
+
+        let mut index = LibraryIndex::default();
+        let summary = index.sync(vec![
+            entry("kept", &kept, modified_time(&kept)),
+            entry("changed", &changed, modified_time(&changed)),
+            entry("gone", &gone, modified_time(&gone)),
+        ]);
+        assert_eq!((summary.added, summary.updated, summary.removed), (3, 0, 0));
+
+        let mut incomplete = entry("changed", &changed, modified_time(&changed));
+        incomplete.state = ClientState::Incomplete;
+        let summary = index.sync(vec![
+            entry("kept", &kept, modified_time(&kept)),
+            incomplete,
+            entry("new", &dir.join("new.torrent"), None),
+        ]);
+        let unchanged = index.unchanged_torrents();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((summary.added, summary.updated, summary.removed), (1, 1, 1));
+        assert_eq!(index.len(), 3);
+        assert!(index.get("gone").is_none());
+        assert_eq!(index.get("changed").unwrap().state, ClientState::Incomplete);
+
+        let mut reused: Vec<&str> = unchanged.values().map(|torrent| torrent.info_hash.as_str()).collect();
+        reused.sort();
+        assert_eq!(reused, vec!["changed", "kept"]);
+    }
+
+    #[test]
+    fn saved_index_loads_back() {
+        let dir = std::env::temp_dir().join(format!("crate-library-save-{}", std::process::id()));
+        let path = dir.join("library.json");
+
+        let mut index = LibraryIndex::default();
+        index.insert(named("a", "Some.Show.S01E01", 100));
+        index.insert(named("b", "Other.Show.S01E01", 200));
+        index.save(&path).unwrap();
+
+        let leftovers: Vec<_> = std::fs::read_dir(&dir).unwrap()
+            .map(|file| file.unwrap().file_name())
+            .filter(|name| name != "library.json")
+            .collect();
+        let loaded = LibraryIndex::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(leftovers.is_empty(), "temporary files left behind: {:?}", leftovers);
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(hashes(loaded.find_by_name("some show s01e01")), vec!["a"]);
+        assert_eq!(hashes(loaded.find_by_size(200)), vec!["b"]);
+        assert_eq!(loaded.get("a").unwrap().files, index.get("a").unwrap().files);
+        assert_eq!(LibraryIndex::load(&dir.join("missing.json")).unwrap().len(), 0);
+    }
+}
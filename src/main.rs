@@ -1,24 +1,37 @@
+mod command;
+mod completion;
 mod config;
 mod torznab;
 mod torrent_client;
 mod indexer;
 mod cross_seed;
+mod daemon;
+mod database;
+mod dry_run;
+mod filter;
+mod health;
+mod injection;
+mod library;
+mod metrics;
+mod notifications;
+mod prowlarr;
+mod rss_poller;
+mod search;
+mod server;
 mod util;
 
-use config::Config;
+use config::{Config, RunMode};
 
 use indexer::Indexer;
-use torrent_client::TorrentClient;
+use torrent_client::TorrentClients;
 use tracing::metadata::LevelFilter;
-use tracing::{info};
-
-use std::path::{Path, PathBuf};
-use std::error::Error;
-use std::vec;
-
-use lava_torrent::torrent::v1::Torrent;
+use tracing::{error, info, warn};
 
+use crate::command::Command;
 use crate::cross_seed::CrossSeed;
+use crate::daemon::Daemon;
+use crate::database::{Database, DecisionQuery};
+use crate::library::LibraryIndex;
 
 use std::sync::Arc;
 
@@ -33,121 +46,169 @@ async fn main() {
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to set global default log subscriber");
 
-    info!("Searching for torrents in: {}", config.torrents_path_str());
-
-    // Get torrent client
-    let torrent_client = get_torrent_client(&config).await;
+    let command = match Command::from_args() {
+        Ok(command) => command,
+        Err(usage) => {
+            eprintln!("Missing a required argument, usage: {}", usage);
+            std::process::exit(2);
+        },
+    };
+    if let Command::Decisions(query) = &command {
+        print_decisions(&config, query);
+        return;
+    }
 
-    // Get indexers
-    let indexers = get_indexers(&config).await;
-    info!("Searching {} trackers: ", indexers.len());
-
-    // Parse torrents from filesystem
-    let torrents = parse_torrents(&config, Arc::clone(&torrent_client)).await;
-    info!("Found {} torrents possibly eligible for cross-seeding.", torrents.len());
-
-    // Store async tasks to wait for them to finish
-    let mut indexer_handles = vec![];
-
-    let seed = Arc::new(CrossSeed::new_arcs(config, indexers, torrent_client));
-    for torrent in torrents {
-        let seed = Arc::clone(&seed);
-        
-        indexer_handles.push(tokio::spawn(async move {
-            seed.search_for_torrent(&torrent).await.unwrap();
-        }));
+    match config.torrents_path_str() {
+        Some(path) => info!("Searching for torrents in: {}", path),
+        None => info!("Searching for torrents in the torrent clients"),
+    }
+    if config.dry_run {
+        warn!("Running as a dry run, nothing will be changed in the torrent client");
     }
 
-    futures::future::join_all(indexer_handles).await;
-}
+    // Get torrent clients
+    let torrent_clients = get_torrent_clients(&config).await;
 
-fn read_torrents(path: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
-    let mut torrents = Vec::new();
-    for entry in path.read_dir()? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() {
-            let filename = path.file_name().unwrap().to_str().unwrap();
-            if filename.ends_with(".torrent") {
-                torrents.push(path);
-            }
-        } else {
-            let mut inner = read_torrents(&path)?;
-            torrents.append(&mut inner);
-        }
+    // Get indexers
+    let (indexers, failed_indexers) = get_indexers(&config).await;
+    info!("Searching {} trackers: ", indexers.iter().filter(|indexer| indexer.enabled).count());
+
+    let library = load_library(&config);
+    let database = open_database(&config);
+    let seed = Arc::new(CrossSeed::new_arcs(Arc::clone(&config), indexers, torrent_clients, library, database));
+    for (indexer, error) in failed_indexers {
+        seed.health().record_failure(&indexer, error);
     }
 
-    return Ok(torrents);
+    match (command, &config.run_mode) {
+        (Command::Search(info_hash), _) => search_info_hash(&seed, &info_hash).await,
+        (_, RunMode::Script) => {
+            // A script run isn't shut down early, it searches every torrent.
+            let (_shutdown, receiver) = tokio::sync::watch::channel(false);
+            search::search_library(&seed, receiver).await
+        },
+        (_, RunMode::Daemon) => Daemon::new(seed).run().await,
+    }
 }
 
-async fn get_indexers(config: &Config) -> Arc<Vec<Indexer>> {
+/// Get the indexers of the config and prowlarr, with the errors of the ones whose client couldn't be created.
+/// Those indexers are disabled, so they are skipped by searches.
+async fn get_indexers(config: &Config) -> (Arc<Vec<Indexer>>, Vec<(String, String)>) {
     let mut indexers = config.indexers.clone();
 
+    // Discover indexers from prowlarr, skipping the ones that are already configured.
+    if let Some(prowlarr) = &config.prowlarr {
+        match prowlarr::ProwlarrClient::new(prowlarr.clone()).discover_indexers().await {
+            Ok(discovered) => {
+                for indexer in discovered {
+                    if !indexers.iter().any(|i| i.name == indexer.name) {
+                        indexers.push(indexer);
+                    }
+                }
+            },
+            Err(err) => error!("Failed to discover indexers from prowlarr: {:?}", err),
+        }
+    }
+
     // Create torznab clients for each indexer.
-    for indexer in indexers.iter_mut() {
-        indexer.create_client().await.unwrap();
+    let mut failed = Vec::new();
+    for indexer in indexers.iter_mut().filter(|indexer| indexer.enabled) {
+        if let Err(err) = indexer.create_client(config.proxy.as_ref(), &config.no_proxy).await {
+            error!("Failed to create the client of indexer {}, skipping it: {:?}", indexer.name, err);
+            indexer.enabled = false;
+            failed.push((indexer.name.clone(), format!("{:?}", err)));
+        }
     }
 
     // Create arc of indexers
-    Arc::new(indexers)
+    (Arc::new(indexers), failed)
+}
+
+async fn get_torrent_clients(config: &Config) -> Arc<TorrentClients> {
+    // Get the torrent clients from the config.
+    let mut torrent_clients = match TorrentClients::from_config(config) {
+        Ok(clients) => clients,
+        Err(err) => {
+            error!("Failed to create the torrent clients: {:?}", err);
+            std::process::exit(1);
+        },
+    };
+    torrent_clients.login().await.unwrap();
+
+    let labels = &config.labels;
+    if labels.copy_tags || !labels.tags.is_empty() {
+        for client in torrent_clients.clients().iter().filter(|client| !client.supports_tags()) {
+            warn!("Torrent client {} doesn't have tags, cross-seeds in it won't get any tags", client.name());
+        }
+    }
+
+    // Torrent clients no longer need to mut, so we can just create an `Arc` without a mutex.
+    Arc::new(torrent_clients)
 }
 
-async fn get_torrent_client(config: &Config) -> Arc<TorrentClient> {
-    // Get a torrent client from the config.
-    let mut torrent_client = torrent_client::TorrentClient::from_config(&config);
-    torrent_client.login(&config).await.unwrap();
+/// Search for a single torrent, and save the library index it was added to.
+async fn search_info_hash(seed: &CrossSeed, info_hash: &str) {
+    // A single search isn't shut down early.
+    let (_shutdown, receiver) = tokio::sync::watch::channel(false);
+    if let Err(err) = search::search_info_hash(seed, info_hash, &receiver).await {
+        error!("Failed to search for {}: {:?}", info_hash, err);
+    }
+
+    if seed.config().dry_run {
+        seed.dry_run_report().print(seed.config().torrent_mode.as_str());
+        return;
+    }
 
-    // Torrent client no longer needs to mut, so we can just create an `Arc` without a mutex.
-    Arc::new(torrent_client)
+    if let Err(err) = seed.library().read().await.save(&seed.config().library_path()) {
+        error!("Failed to save library index: {:?}", err);
+    }
 }
 
-async fn parse_torrents(config: &Config, torrent_client: Arc<TorrentClient>) -> Vec<Torrent> {
-    // Read the torrents from the config as `PathBuf`s
-    let torrent_files = read_torrents(config.torrents_path()).unwrap();
-    info!("Found {} torrent files...", torrent_files.len());
-
-    // Parse the torrent files as `Torrent` structs.
-    info!("Parsing all torrent files...");
-    let mut stop = stopwatch::Stopwatch::start_new();
-
-    // Get the torrents and from the paths
-    let mut torrents: Vec<Result<Torrent, lava_torrent::LavaTorrentError>> = torrent_files.iter()
-        .map(|path| Torrent::read_from_file(path))
-        .collect();
-    stop.stop();
-    
-    info!("Took {} seconds to parse all torrents", stop.elapsed().as_secs());
-    drop(stop); // Drop for memory
-
-    // Remove the torrents that failed to be read from the file, and
-    // are not in the download client.
-    //
-    // NOTE: It might be better to get all torrents on the client and check that the torrents are on the
-    // client locally.
-
-    /* let torrents = torrents.iter()
-        .map(|res| res.map(|torrent| {
-            let info = futures::executor::block_on(torrent_client.get_torrent_info(&torrent))
-                .unwrap_or(None);
-
-            torrent.ha
-            //(torrent, info)
-        })).collect(); */
-    torrents.retain(|torrent| {
-        if let Ok(torrent) = torrent {
-            let info = futures::executor::block_on(torrent_client.get_torrent_info(&torrent))
-                .unwrap_or(None);
-
-            info.is_some()
-        } else {
-            false
-        }
-    });
+/// Load the persisted library index, it's synced with the local torrents when they're parsed.
+fn load_library(config: &Config) -> LibraryIndex {
+    LibraryIndex::load(&config.library_path()).unwrap_or_else(|err| {
+        warn!("Failed to load library index, rebuilding it: {:?}", err);
+        LibraryIndex::default()
+    })
+}
 
-    // Unwrap the results, all errored ones were removed from the `.retain`
-    let torrents: Vec<Torrent> = torrents.iter()
-        .map(|res| res.as_ref().unwrap().clone())
-        .collect();
+/// Open the local database that stores the search history and decision log. A dry run only reads an existing
+/// database, so it doesn't create the data directory or the database.
+fn open_database(config: &Config) -> Option<Database> {
+    let db = match config.dry_run {
+        true => Database::open_read_only(&config.database_path()),
+        false => Database::open(&config.database_path()).map(Some),
+    };
+
+    match db {
+        Ok(db) => db,
+        Err(err) => {
+            error!("Failed to open database, searches and decisions won't be recorded: {:?}", err);
+            None
+        }
+    }
+}
 
-    torrents
+/// Print the decisions in the decision log that match a query.
+fn print_decisions(config: &Config, query: &DecisionQuery) {
+    let decisions = match open_database(config).map(|db| db.query_decisions(query)) {
+        Some(Ok(decisions)) => decisions,
+        Some(Err(err)) => {
+            error!("Failed to query decisions: {:?}", err);
+            return;
+        },
+        None => return,
+    };
+
+    for decision in decisions {
+        let time = chrono::NaiveDateTime::from_timestamp_opt(decision.created_at, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+
+        println!("{}  {:<18} {} ({})", time, decision.decision.as_str(), decision.name, decision.info_hash);
+        println!("    {} on {} ({})", decision.candidate_name, decision.indexer, decision.candidate_hash);
+        if let Some(detail) = decision.detail {
+            println!("    {}", detail);
+        }
+    }
 }
\ No newline at end of file
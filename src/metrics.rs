@@ -0,0 +1,105 @@
+use once_cell::sync::Lazy;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+
+/// The metrics of cross-seed, exposed in the prometheus text format.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+
+    /// Searches sent to each indexer.
+    pub indexer_searches: IntCounterVec,
+    /// Results returned by each indexer.
+    pub indexer_results: IntCounterVec,
+    /// Torrent files downloaded from each indexer.
+    pub indexer_downloads: IntCounterVec,
+    /// Failed searches and downloads of each indexer.
+    pub indexer_errors: IntCounterVec,
+    /// How long searches to each indexer took.
+    pub indexer_latency: HistogramVec,
+
+    /// Local torrents that were parsed to be searched.
+    pub torrents_scanned: IntCounter,
+    /// Local torrents that were excluded by a filter rule, by rule.
+    pub torrents_excluded: IntCounterVec,
+    /// Cross-seeds that were found, by torrent mode.
+    pub torrents_matched: IntCounterVec,
+    /// Cross-seeds that were added to the client, by torrent mode.
+    pub torrents_injected: IntCounterVec,
+    /// Failed requests to the torrent client.
+    pub client_errors: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("cross_seed".to_string()), None)
+            .expect("Failed to create metrics registry");
+
+        let counter_vec = |name: &str, help: &str, label: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &[label]).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounter::new(name, help).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+
+        let indexer_latency = HistogramVec::new(
+            HistogramOpts::new("indexer_search_duration_seconds", "How long searches to an indexer took"),
+            &["indexer"],
+        ).unwrap();
+        registry.register(Box::new(indexer_latency.clone())).unwrap();
+
+        Self {
+            indexer_searches: counter_vec("indexer_searches_total", "Searches sent to an indexer", "indexer"),
+            indexer_results: counter_vec("indexer_results_total", "Results returned by an indexer", "indexer"),
+            indexer_downloads: counter_vec("indexer_downloads_total", "Torrent files downloaded from an indexer", "indexer"),
+            indexer_errors: counter_vec("indexer_errors_total", "Failed searches and downloads of an indexer", "indexer"),
+            indexer_latency,
+            torrents_scanned: counter("torrents_scanned_total", "Local torrents parsed to be searched"),
+            torrents_excluded: counter_vec("torrents_excluded_total", "Local torrents excluded by a filter rule", "rule"),
+            torrents_matched: counter_vec("torrents_matched_total", "Cross-seeds found", "mode"),
+            torrents_injected: counter_vec("torrents_injected_total", "Cross-seeds added to the torrent client", "mode"),
+            client_errors: counter("client_errors_total", "Failed requests to the torrent client"),
+            registry,
+        }
+    }
+
+    /// Encode all metrics in the prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = vec![];
+
+        if let Err(err) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", err);
+        }
+
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_registered_counters() {
+        // A separate registry, the global one is shared with every other test.
+        let metrics = Metrics::new();
+        metrics.client_errors.inc();
+        metrics.client_errors.inc();
+        metrics.torrents_injected.with_label_values(&["inject"]).inc();
+        metrics.indexer_latency.with_label_values(&["Tracker"]).observe(0.5);
+
+        let encoded = metrics.encode();
+
+        assert!(encoded.contains("# TYPE cross_seed_client_errors_total counter"));
+        assert!(encoded.contains("cross_seed_client_errors_total 2"));
+        assert!(encoded.contains("cross_seed_torrents_injected_total{mode=\"inject\"} 1"));
+        assert!(encoded.contains("cross_seed_torrents_scanned_total 0"));
+        assert!(encoded.contains("cross_seed_indexer_search_duration_seconds_count{indexer=\"Tracker\"} 1"));
+        // Labelled counters only show up once a label was used.
+        assert!(!encoded.contains("cross_seed_torrents_matched_total"));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde_json::json;
+use tracing::{debug, error};
+
+use crate::config::{NotificationConfig, NotificationEvent, NotificationKind};
+
+/// A notification about something cross-seed did.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub event: NotificationEvent,
+    /// The values that can be used in message templates, ex: `name`, `indexer`.
+    pub fields: BTreeMap<&'static str, String>,
+}
+
+impl Notification {
+    pub fn new(event: NotificationEvent) -> Self {
+        Self {
+            event,
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn field(mut self, key: &'static str, value: impl ToString) -> Self {
+        self.fields.insert(key, value.to_string());
+        self
+    }
+
+    /// Render a message template, replacing `{field}` with the value of the field.
+    pub fn render(&self, template: &str) -> String {
+        self.fields.iter().fold(template.to_string(), |message, (key, value)| {
+            message.replace(&format!("{{{}}}", key), value)
+        })
+    }
+}
+
+/// How long a sink gets to take a notification. Notifications are sent in the middle of searches, so a
+/// hanging sink must not hold them up.
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends notifications to the configured sinks.
+pub struct Notifier {
+    sinks: Vec<NotificationConfig>,
+    http: reqwest::Client,
+    timeout: Duration,
+}
+
+impl Notifier {
+    pub fn new(sinks: Vec<NotificationConfig>) -> Self {
+        Self {
+            sinks,
+            http: reqwest::Client::new(),
+            timeout: NOTIFICATION_TIMEOUT,
+        }
+    }
+
+    /// Send a notification to every sink that wants it. Failures are logged, but
+    /// don't stop the notification from being sent to the other sinks.
+    pub async fn notify(&self, notification: Notification) {
+        for sink in self.sinks.iter().filter(|sink| sink.wants(notification.event)) {
+            let template = sink.templates.get(&notification.event)
+                .map(|template| template.as_str())
+                .unwrap_or_else(|| notification.event.default_template());
+            let message = notification.render(template);
+
+            debug!("Sending {} notification to {:?} sink", notification.event.as_str(), sink.kind);
+            if let Err(err) = self.send(sink, &notification, &message).await {
+                error!("Failed to send {} notification to {:?} sink: {:?}", notification.event.as_str(), sink.kind, err);
+            }
+        }
+    }
+
+    async fn send(&self, sink: &NotificationConfig, notification: &Notification, message: &str) -> Result<(), NotificationError> {
+        match sink.kind {
+            NotificationKind::Webhook => {
+                let mut payload = json!({
+                    "event": notification.event.as_str(),
+                    "message": message,
+                });
+                for (key, value) in notification.fields.iter() {
+                    payload[*key] = json!(value);
+                }
+
+                self.post(sink, &payload).await
+            },
+            NotificationKind::Discord => {
+                let payload = json!({
+                    "username": "cross-seed",
+                    "content": message,
+                });
+
+                self.post(sink, &payload).await
+            },
+            NotificationKind::Command => {
+                let command = sink.command.as_ref().ok_or(NotificationError::MissingCommand)?;
+
+                let mut process = shell_command(command);
+                process.env("CROSS_SEED_EVENT", notification.event.as_str())
+                    .env("CROSS_SEED_MESSAGE", message)
+                    // The command is killed if it doesn't finish in time.
+                    .kill_on_drop(true);
+                for (key, value) in notification.fields.iter() {
+                    process.env(format!("CROSS_SEED_{}", key.to_uppercase()), value);
+                }
+
+                let status = tokio::time::timeout(self.timeout, process.status()).await
+                    .map_err(|_| NotificationError::TimedOut)??;
+                if !status.success() {
+                    return Err(NotificationError::CommandFailed(status));
+                }
+
+                Ok(())
+            },
+        }
+    }
+
+    async fn post(&self, sink: &NotificationConfig, payload: &serde_json::Value) -> Result<(), NotificationError> {
+        let url = sink.url.as_ref().ok_or(NotificationError::MissingUrl)?;
+
+        self.http.post(url)
+            .timeout(self.timeout)
+            .json(payload)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+/// Create a process that runs a command with the shell of the platform.
+fn shell_command(command: &str) -> tokio::process::Command {
+    #[cfg(unix)]
+    {
+        let mut process = tokio::process::Command::new("sh");
+        process.arg("-c").arg(command);
+        process
+    }
+
+    #[cfg(not(unix))]
+    {
+        let mut process = tokio::process::Command::new("cmd");
+        process.arg("/C").arg(command);
+        process
+    }
+}
+
+#[derive(Debug)]
+pub enum NotificationError {
+    MissingUrl,
+    MissingCommand,
+    HttpError(reqwest::Error),
+    IoError(std::io::Error),
+    CommandFailed(std::process::ExitStatus),
+    /// The command didn't finish before the timeout.
+    TimedOut,
+}
+
+impl From<reqwest::Error> for NotificationError {
+    fn from(e: reqwest::Error) -> Self {
+        NotificationError::HttpError(e)
+    }
+}
+
+impl From<std::io::Error> for NotificationError {
+    fn from(e: std::io::Error) -> Self {
+        NotificationError::IoError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{routing::post, Json, Router};
+    use reqwest::StatusCode;
+    use serde_json::Value;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// Start a local server that answers every post with `status`, after `delay`. Returns its url and the
+    /// received payloads.
+    fn serve(status: StatusCode, delay: Duration) -> (String, mpsc::UnboundedReceiver<Value>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let app = Router::new().route("/", post(move |Json(payload): Json<Value>| {
+            let sender = sender.clone();
+            async move {
+                tokio::time::sleep(delay).await;
+                sender.send(payload).ok();
+                status
+            }
+        }));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        (url, receiver)
+    }
+
+    fn sink(kind: NotificationKind, url: Option<String>, command: Option<&str>) -> NotificationConfig {
+        NotificationConfig {
+            kind,
+            url,
+            command: command.map(|command| command.to_string()),
+            events: vec![],
+            templates: HashMap::new(),
+        }
+    }
+
+    fn injected() -> Notification {
+        Notification::new(NotificationEvent::Injected)
+            .field("name", "Show.S01")
+            .field("indexer", "Tracker")
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_message_and_fields() {
+        let (url, mut received) = serve(StatusCode::OK, Duration::ZERO);
+        let mut webhook = sink(NotificationKind::Webhook, Some(url), None);
+        webhook.templates.insert(NotificationEvent::Injected, String::from("Added {name} from {indexer}"));
+
+        Notifier::new(vec![webhook]).notify(injected()).await;
+
+        let payload = received.recv().await.unwrap();
+        assert_eq!(payload["event"], "injected");
+        assert_eq!(payload["message"], "Added Show.S01 from Tracker");
+        assert_eq!(payload["indexer"], "Tracker");
+    }
+
+    #[tokio::test]
+    async fn discord_posts_content() {
+        let (url, mut received) = serve(StatusCode::NO_CONTENT, Duration::ZERO);
+        let notifier = Notifier::new(vec![sink(NotificationKind::Discord, Some(url), None)]);
+
+        notifier.notify(Notification::new(NotificationEvent::ClientFailing)
+            .field("client", "qBittorrent")
+            .field("error", "refused")).await;
+
+        let payload = received.recv().await.unwrap();
+        assert_eq!(payload["username"], "cross-seed");
+        assert_eq!(payload["content"], "Torrent client qBittorrent is failing: refused");
+    }
+
+    #[tokio::test]
+    async fn failing_sinks_are_reported() {
+        let (url, _received) = serve(StatusCode::INTERNAL_SERVER_ERROR, Duration::ZERO);
+        let notifier = Notifier::new(vec![]);
+
+        let result = notifier.send(&sink(NotificationKind::Webhook, Some(url), None), &injected(), "message").await;
+        assert!(matches!(result, Err(NotificationError::HttpError(err)) if err.status() == Some(StatusCode::INTERNAL_SERVER_ERROR)));
+
+        let result = notifier.send(&sink(NotificationKind::Webhook, None, None), &injected(), "message").await;
+        assert!(matches!(result, Err(NotificationError::MissingUrl)));
+    }
+
+    #[tokio::test]
+    async fn hanging_webhooks_time_out() {
+        let (url, _received) = serve(StatusCode::OK, Duration::from_secs(5));
+        let mut notifier = Notifier::new(vec![]);
+        notifier.timeout = Duration::from_millis(100);
+
+        let result = notifier.send(&sink(NotificationKind::Webhook, Some(url), None), &injected(), "message").await;
+        assert!(matches!(result, Err(NotificationError::HttpError(err)) if err.is_timeout()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn command_gets_fields_as_env() {
+        let path = std::env::temp_dir().join(format!("crate-notification-{}", std::process::id()));
+        let command = format!("printf '%s %s' \"$CROSS_SEED_EVENT\" \"$CROSS_SEED_NAME\" > {}", path.display());
+        let notifier = Notifier::new(vec![]);
+
+        notifier.send(&sink(NotificationKind::Command, None, Some(&command)), &injected(), "message").await.unwrap();
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(output, "injected Show.S01");
+
+        let result = notifier.send(&sink(NotificationKind::Command, None, Some("exit 3")), &injected(), "message").await;
+        assert!(matches!(result, Err(NotificationError::CommandFailed(status)) if status.code() == Some(3)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hanging_commands_time_out() {
+        let mut notifier = Notifier::new(vec![]);
+        notifier.timeout = Duration::from_millis(100);
+
+        let result = notifier.send(&sink(NotificationKind::Command, None, Some("sleep 5")), &injected(), "message").await;
+        assert!(matches!(result, Err(NotificationError::TimedOut)));
+    }
+}
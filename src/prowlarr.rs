@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tracing::{debug, info};
+
+use crate::config::ProwlarrConfig;
+use crate::indexer::Indexer;
+
+/// An indexer as returned by prowlarr's `/api/v1/indexer` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProwlarrIndexer {
+    pub id: u32,
+    pub name: String,
+    pub enable: bool,
+    pub protocol: String,
+    #[serde(default)]
+    pub tags: Vec<u32>,
+}
+
+/// A tag as returned by prowlarr's `/api/v1/tag` endpoint.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProwlarrTag {
+    pub id: u32,
+    pub label: String,
+}
+
+pub struct ProwlarrClient {
+    http: reqwest::Client,
+    config: ProwlarrConfig,
+}
+
+impl ProwlarrClient {
+    pub fn new(config: ProwlarrConfig) -> Self {
+        ProwlarrClient {
+            http: reqwest::Client::new(),
+            config,
+        }
+    }
+
+    /// The base url of prowlarr without a trailing slash.
+    fn base_url(&self) -> &str {
+        self.config.url.trim_end_matches('/')
+    }
+
+    /// Send a GET request to the prowlarr api and deserialize the json response.
+    async fn get<T: serde::de::DeserializeOwned>(&self, endpoint: &str) -> Result<T, ProwlarrError> {
+        let url = format!("{}/api/v1/{}", self.base_url(), endpoint);
+        debug!("Prowlarr url: {}", url);
+
+        let res = self.http.get(url)
+            .header("X-Api-Key", &self.config.api_key)
+            .send().await?
+            .error_for_status()?;
+
+        Ok(res.json().await?)
+    }
+
+    /// Get all indexers from prowlarr.
+    pub async fn get_indexers(&self) -> Result<Vec<ProwlarrIndexer>, ProwlarrError> {
+        self.get("indexer").await
+    }
+
+    /// Get all tags from prowlarr.
+    pub async fn get_tags(&self) -> Result<Vec<ProwlarrTag>, ProwlarrError> {
+        self.get("tag").await
+    }
+
+    /// Get the torznab url of an indexer proxied through prowlarr.
+    pub fn torznab_url(&self, indexer: &ProwlarrIndexer) -> String {
+        format!("{}/{}/api", self.base_url(), indexer.id)
+    }
+
+    /// Check if an indexer passes the include and exclude filters of the config.
+    fn is_indexer_wanted(&self, indexer: &ProwlarrIndexer, tags: &[&String]) -> bool {
+        let matches_name = |names: &Vec<String>| names.iter()
+            .any(|name| name.eq_ignore_ascii_case(&indexer.name));
+        let matches_tag = |filter: &Vec<String>| filter.iter()
+            .any(|filter_tag| tags.iter().any(|tag| tag.eq_ignore_ascii_case(filter_tag)));
+
+        if matches_name(&self.config.exclude) || matches_tag(&self.config.exclude_tags) {
+            return false;
+        }
+
+        (self.config.include.is_empty() || matches_name(&self.config.include))
+            && (self.config.include_tags.is_empty() || matches_tag(&self.config.include_tags))
+    }
+
+    /// Discover the enabled torrent indexers in prowlarr and create `Indexer`s for them.
+    pub async fn discover_indexers(&self) -> Result<Vec<Indexer>, ProwlarrError> {
+        let tags: HashMap<u32, String> = self.get_tags().await?
+            .into_iter()
+            .map(|tag| (tag.id, tag.label))
+            .collect();
+
+        let indexers: Vec<Indexer> = self.get_indexers().await?
+            .into_iter()
+            .filter(|indexer| indexer.enable && indexer.protocol == "torrent")
+            .filter(|indexer| {
+                let indexer_tags: Vec<&String> = indexer.tags.iter()
+                    .filter_map(|id| tags.get(id))
+                    .collect();
+
+                self.is_indexer_wanted(indexer, &indexer_tags)
+            })
+            .map(|indexer| Indexer {
+                name: indexer.name.clone(),
+                enabled: true,
+                url: self.torznab_url(&indexer),
+                api_key: self.config.api_key.clone(),
+                proxy: None,
+                client: None,
+            })
+            .collect();
+
+        info!("Discovered {} indexers from prowlarr", indexers.len());
+
+        Ok(indexers)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProwlarrError {
+    HttpError(reqwest::Error),
+}
+
+impl From<reqwest::Error> for ProwlarrError {
+    fn from(e: reqwest::Error) -> Self {
+        ProwlarrError::HttpError(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::HeaderMap, routing::get, Json, Router};
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Answer with `body` if the request has the api key `key`, like prowlarr does.
+    fn with_api_key(headers: &HeaderMap, body: Value) -> (StatusCode, Json<Value>) {
+        match headers.get("X-Api-Key").and_then(|key| key.to_str().ok()) {
+            Some("key") => (StatusCode::OK, Json(body)),
+            _ => (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Unauthorized" }))),
+        }
+    }
+
+    /// Start a local prowlarr with indexers that are disabled, for usenet, and tagged. Returns its url.
+    fn serve() -> String {
+        let app = Router::new()
+            .route("/api/v1/indexer", get(|headers: HeaderMap| async move {
+                with_api_key(&headers, json!([
+                    { "id": 1, "name": "A", "enable": true, "protocol": "torrent", "tags": [1] },
+                    { "id": 2, "name": "B", "enable": false, "protocol": "torrent", "tags": [1] },
+                    { "id": 3, "name": "C", "enable": true, "protocol": "usenet", "tags": [1] },
+                    { "id": 4, "name": "D", "enable": true, "protocol": "torrent", "tags": [1, 2] },
+                    { "id": 5, "name": "E", "enable": true, "protocol": "torrent" },
+                ]))
+            }))
+            .route("/api/v1/tag", get(|headers: HeaderMap| async move {
+                with_api_key(&headers, json!([
+                    { "id": 1, "label": "Private" },
+                    { "id": 2, "label": "slow" },
+                ]))
+            }));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        url
+    }
+
+    fn client(url: &str) -> ProwlarrClient {
+        ProwlarrClient::new(ProwlarrConfig {
+            url: url.to_string(),
+            api_key: String::from("key"),
+            include: vec![],
+            exclude: vec![],
+            include_tags: vec![],
+            exclude_tags: vec![],
+        })
+    }
+
+    fn indexer(name: &str) -> ProwlarrIndexer {
+        ProwlarrIndexer {
+            id: 7,
+            name: name.to_string(),
+            enable: true,
+            protocol: String::from("torrent"),
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn builds_torznab_url() {
+        assert_eq!(client("http://localhost:9696").torznab_url(&indexer("A")), "http://localhost:9696/7/api");
+        assert_eq!(client("http://localhost:9696/").torznab_url(&indexer("A")), "http://localhost:9696/7/api");
+        assert_eq!(client("http://host/prowlarr/").torznab_url(&indexer("A")), "http://host/prowlarr/7/api");
+    }
+
+    #[test]
+    fn filters_by_name() {
+        let mut client = client("http://localhost:9696");
+        assert!(client.is_indexer_wanted(&indexer("A"), &[]));
+
+        client.config.include = vec![String::from("a"), String::from("B")];
+        client.config.exclude = vec![String::from("b")];
+        assert!(client.is_indexer_wanted(&indexer("A"), &[]));
+        assert!(!client.is_indexer_wanted(&indexer("B"), &[]));
+        assert!(!client.is_indexer_wanted(&indexer("C"), &[]));
+    }
+
+    #[test]
+    fn filters_by_tag() {
+        let mut client = client("http://localhost:9696");
+        let private = String::from("Private");
+        let slow = String::from("slow");
+
+        client.config.include_tags = vec![String::from("private")];
+        client.config.exclude_tags = vec![String::from("SLOW")];
+        assert!(client.is_indexer_wanted(&indexer("A"), &[&private]));
+        assert!(!client.is_indexer_wanted(&indexer("A"), &[]));
+        assert!(!client.is_indexer_wanted(&indexer("A"), &[&private, &slow]));
+
+        // Both the name and tag filters have to pass.
+        client.config.include = vec![String::from("B")];
+        assert!(!client.is_indexer_wanted(&indexer("A"), &[&private]));
+    }
+
+    #[tokio::test]
+    async fn discovers_enabled_torrent_indexers() {
+        let url = serve();
+        let mut client = client(&url);
+        client.config.include_tags = vec![String::from("private")];
+        client.config.exclude_tags = vec![String::from("SLOW")];
+
+        let indexers = client.discover_indexers().await.unwrap();
+        let names: Vec<&str> = indexers.iter().map(|indexer| indexer.name.as_str()).collect();
+        assert_eq!(names, vec!["A"]);
+        assert_eq!(indexers[0].url, format!("{}/1/api", url));
+        assert_eq!(indexers[0].api_key, "key");
+    }
+
+    #[tokio::test]
+    async fn sends_api_key() {
+        let url = serve();
+
+        let names: Vec<String> = client(&url).discover_indexers().await.unwrap()
+            .into_iter()
+            .map(|indexer| indexer.name)
+            .collect();
+        assert_eq!(names, vec!["A", "D", "E"]);
+
+        let mut client = client(&url);
+        client.config.api_key = String::from("wrong");
+        let result = client.discover_indexers().await;
+        assert!(matches!(result, Err(ProwlarrError::HttpError(err)) if err.status() == Some(StatusCode::UNAUTHORIZED)));
+    }
+}
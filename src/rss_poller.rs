@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+use tracing::{debug, error, info};
+
+use crate::cross_seed::{CrossSeed, CrossSeedError, MatchOutcome};
+use crate::indexer::Indexer;
+use crate::torznab::{GenericSearchParameters, SearchFunction};
+
+/// How many links of checked releases are remembered per indexer. Feeds only return their most recent
+/// releases, so older links won't show up again.
+const MAX_SEEN_RELEASES: usize = 1000;
+
+/// Links of the releases that were already checked on an indexer, the oldest are forgotten first.
+#[derive(Debug, Default)]
+struct SeenReleases {
+    links: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl SeenReleases {
+    fn contains(&self, link: &str) -> bool {
+        self.links.contains(link)
+    }
+
+    /// Remember a link. Returns false if it was already seen.
+    fn insert(&mut self, link: &str) -> bool {
+        if !self.links.insert(link.to_string()) {
+            return false;
+        }
+
+        self.order.push_back(link.to_string());
+        if self.order.len() > MAX_SEEN_RELEASES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.links.remove(&oldest);
+            }
+        }
+
+        true
+    }
+}
+
+/// Polls the recent releases of every indexer and matches them against the local library.
+pub struct RssPoller {
+    seed: Arc<CrossSeed>,
+    /// Links of the releases that were already checked, per indexer.
+    seen: Mutex<HashMap<String, SeenReleases>>,
+}
+
+impl RssPoller {
+    pub fn new(seed: Arc<CrossSeed>) -> Self {
+        Self {
+            seed,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Poll all enabled indexers once.
+    pub async fn poll(&self) {
+        for indexer in self.seed.indexers().iter().filter(|i| i.enabled) {
+            if let Err(err) = self.poll_indexer(indexer).await {
+                error!("Failed to poll rss feed of {}: {:?}", indexer.name, err);
+            }
+        }
+    }
+
+    /// Poll the recent releases of an indexer and check the ones that weren't seen before.
+    ///
+    /// A release is only remembered once it's handled: it was added, or it can't be a cross-seed. Releases that
+    /// failed to be checked, or that match a torrent that can't be cross-seeded yet, are checked again next poll.
+    ///
+    /// Returns the amount of releases that were added as cross-seeds.
+    pub async fn poll_indexer(&self, indexer: &Indexer) -> Result<usize, CrossSeedError> {
+        // A search without a query returns the most recent releases of the indexer.
+        let client = indexer.client.as_ref().unwrap().read().await;
+        let releases = match client.search(SearchFunction::Search, GenericSearchParameters::default()).await {
+            Ok(releases) => releases,
+            Err(err) => {
+                self.seed.indexer_failed(&indexer.name, format!("{:?}", err)).await;
+                return Err(err.into());
+            }
+        };
+        self.seed.health().record_success(&indexer.name);
+        let http = client.http().clone();
+        drop(client);
+
+        let new_releases: Vec<_> = {
+            let seen = self.seen.lock().await;
+            match seen.get(&indexer.name) {
+                Some(seen) => releases.into_iter()
+                    .filter(|release| !seen.contains(&release.link))
+                    .collect(),
+                None => releases,
+            }
+        };
+        debug!("Found {} new releases on {}", new_releases.len(), indexer.name);
+
+        let mut matched = 0;
+        for release in new_releases.iter() {
+            let handled = match self.seed.match_release(&indexer.name, &http, release).await {
+                Ok(MatchOutcome::Added) => {
+                    matched += 1;
+                    true
+                },
+                Ok(MatchOutcome::NotAdded) => false,
+                Ok(_) => true,
+                Err(err) => {
+                    error!("Failed to check release {} from {}: {:?}", release.name, indexer.name, err);
+                    false
+                },
+            };
+
+            if handled {
+                self.seen.lock().await
+                    .entry(indexer.name.clone())
+                    .or_default()
+                    .insert(&release.link);
+            }
+        }
+
+        if matched > 0 {
+            info!("Added {} cross-seeds from the rss feed of {}", matched, indexer.name);
+        }
+
+        Ok(matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_oldest_releases() {
+        let mut seen = SeenReleases::default();
+        assert!(!seen.contains("first"));
+        assert!(seen.insert("first"));
+        assert!(seen.contains("first"));
+        assert!(!seen.insert("first"));
+
+        for i in 0..MAX_SEEN_RELEASES {
+            assert!(seen.insert(&i.to_string()));
+        }
+
+        assert_eq!(seen.links.len(), MAX_SEEN_RELEASES);
+        assert!(!seen.contains("first"));
+        assert!(seen.insert("first"));
+        assert!(!seen.insert(&(MAX_SEEN_RELEASES - 1).to_string()));
+    }
+}
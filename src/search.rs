@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::StreamExt;
+use lava_torrent::torrent::v1::Torrent;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{Config, NotificationEvent};
+use crate::cross_seed::{CrossSeed, CrossSeedError};
+use crate::filter::{FilterSummary, TorrentFilter};
+use crate::library::{LibraryEntry, TorrentMetadata};
+use crate::notifications::Notification;
+use crate::torrent_client::{ClientError, TorrentClient, TorrentClients, TorrentInfo};
+
+/// The amount of torrents that are searched for at the same time.
+const CONCURRENT_SEARCHES: usize = 4;
+
+/// Parse the local torrents, update the library index with them, and search
+/// the indexers for the ones that pass the filter rules. Once `shutdown` is set, the torrents that weren't searched yet are skipped.
+pub async fn search_library(seed: &Arc<CrossSeed>, shutdown: watch::Receiver<bool>) {
+    let started = std::time::Instant::now();
+    let torrents = filter_torrents(seed, refresh_library(seed).await).await;
+    let searched = torrents.len();
+
+    // The shutdown is checked when each torrent's search starts, so only a few torrents are searched at a time.
+    let shutdown = &shutdown;
+    let results: Vec<Option<Result<usize, CrossSeedError>>> = futures::stream::iter(torrents)
+        .map(|torrent| async move {
+            if *shutdown.borrow() {
+                return None;
+            }
+
+            let result = seed.search_for_torrent(&torrent, shutdown).await;
+            if let Err(err) = &result {
+                error!("Failed to search for {}: {:?}", torrent.name, err);
+            }
+
+            Some(result)
+        })
+        .buffer_unordered(CONCURRENT_SEARCHES)
+        .collect()
+        .await;
+
+    let (mut injected, mut failed, mut skipped) = (0, 0, 0);
+    for result in results {
+        match result {
+            Some(Ok(count)) => injected += count,
+            None => skipped += 1,
+            Some(Err(_)) => failed += 1,
+        }
+    }
+
+    let searched = searched - skipped;
+    let duration = std::time::Duration::from_secs(started.elapsed().as_secs());
+    info!("Searched {} torrents in {:?}, added {} cross-seeds", searched, duration, injected);
+    if skipped > 0 {
+        warn!("Skipped searching {} torrents because of the shutdown", skipped);
+    }
+
+    // A dry run has no side effects, so the run isn't notified either.
+    if seed.config().dry_run {
+        seed.dry_run_report().print(seed.config().torrent_mode.as_str());
+        return;
+    }
+
+    seed.notifier().notify(Notification::new(NotificationEvent::RunFinished)
+        .field("searched", searched)
+        .field("injected", injected)
+        .field("failed", failed)
+        .field("duration", format!("{:?}", duration))).await;
+}
+
+/// Search the indexers for a single local torrent, ex: one that just finished downloading.
+///
+/// The torrent is looked up in the library index first, then in the torrents path or the clients.
+/// Returns false if the torrent wasn't found, isn't in any client, or is excluded by the filter rules.
+pub async fn search_info_hash(seed: &CrossSeed, info_hash: &str, shutdown: &watch::Receiver<bool>) -> Result<bool, CrossSeedError> {
+    let info_hash = info_hash.trim().to_lowercase();
+
+    // The torrent may have been added to a client after the last snapshot was taken.
+    seed.torrent_clients().refresh().await?;
+
+    let torrent = match find_torrent(seed, &info_hash).await {
+        Some(found) => found,
+        None => {
+            warn!("Failed to find a torrent file for {}", info_hash);
+            return Ok(false);
+        }
+    };
+
+    let info = match seed.torrent_clients().find_torrent(&torrent.info_hash).await? {
+        Some((_, info)) => info,
+        None => {
+            warn!("Torrent {} isn't in any client, skipping...", torrent.name);
+            return Ok(false);
+        }
+    };
+
+    // Add the torrent to the library so releases from rss and announces are matched against it, even if
+    // the filter rules exclude it from searches.
+    seed.library().write().await.insert(LibraryEntry::new(&torrent, Some(&info)));
+
+    match TorrentFilter::new(&seed.config().filters) {
+        Ok(filter) => if let Err(rule) = filter.check(&torrent, &info) {
+            info!("Torrent {} is excluded by the {} rule, skipping...", torrent.name, rule.as_str());
+            return Ok(false);
+        },
+        Err(err) => {
+            error!("Invalid filter regex, not searching any torrents: {}", err);
+            return Ok(false);
+        }
+    }
+
+    info!("Searching for {}...", torrent.name);
+    seed.search_for_torrent(&torrent, shutdown).await?;
+
+    Ok(true)
+}
+
+/// Find the torrent file of a local torrent by its info hash.
+async fn find_torrent(seed: &CrossSeed, info_hash: &str) -> Option<TorrentMetadata> {
+    if let Some(entry) = seed.library().read().await.get(info_hash) {
+        match entry.load_metadata() {
+            Ok(torrent) => return Some(torrent),
+            Err(err) => debug!("Failed to load torrent of library entry {}: {:?}", info_hash, err),
+        }
+    }
+
+    let torrents_path = match seed.config().torrents_path() {
+        Some(path) => path,
+        None => {
+            let (client, info) = seed.torrent_clients().find_torrent(info_hash).await.ok()??;
+            return export_torrent(client, info, &seed.config().exported_torrents_path()).await;
+        }
+    };
+
+    let mut paths = read_torrents(torrents_path).ok()?;
+
+    // Clients like qbittorrent name the torrent files after their info hash, so try those first.
+    paths.sort_by_key(|path| path.file_stem().map_or(true, |stem| !stem.eq_ignore_ascii_case(info_hash)));
+
+    paths.into_iter()
+        .filter_map(|path| TorrentMetadata::read(path).ok())
+        .find(|torrent| torrent.info_hash == info_hash)
+}
+
+/// Parse the local torrents and sync the library index with them, then save the index.
+/// Returns the parsed torrents.
+pub async fn refresh_library(seed: &CrossSeed) -> Vec<TorrentMetadata> {
+    let config = seed.config();
+
+    // Take one snapshot of the clients for the run, the local torrents are looked up in it.
+    match seed.torrent_clients().refresh().await {
+        Ok(torrents) => info!("Found {} torrents in the torrent clients", torrents.len()),
+        Err(err) => {
+            error!("Failed to get the torrents in the clients: {:?}", err);
+            crate::metrics::METRICS.client_errors.inc();
+            return vec![];
+        }
+    }
+
+    // Parse torrents from the filesystem or the clients. The torrent files that weren't modified since they
+    // were indexed aren't parsed again.
+    let unchanged = seed.library().read().await.unchanged_torrents();
+    let torrents = match parse_torrents(config, seed.torrent_clients(), unchanged).await {
+        Ok(torrents) => torrents,
+        Err(err) => {
+            // Syncing without the local torrents would empty the library index.
+            error!("Failed to read the local torrents: {:?}", err);
+            return vec![];
+        }
+    };
+    info!("Found {} torrents possibly eligible for cross-seeding.", torrents.len());
+
+    // Update the persisted index of the local torrents for matching releases against them.
+    let (entries, torrents): (Vec<LibraryEntry>, Vec<TorrentMetadata>) = torrents.into_iter().unzip();
+
+    let mut library = seed.library().write().await;
+    let summary = library.sync(entries);
+    info!("Library index has {} torrents ({} added, {} updated, {} removed)",
+        library.len(), summary.added, summary.updated, summary.removed);
+
+    if !config.dry_run {
+        if let Err(err) = library.save(&config.library_path()) {
+            error!("Failed to save library index: {:?}", err);
+        }
+    }
+
+    torrents
+}
+
+fn read_torrents(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut torrents = Vec::new();
+    for entry in path.read_dir()? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_file() {
+            if path.extension().map_or(false, |ext| ext == "torrent") {
+                torrents.push(path);
+            }
+        } else {
+            let mut inner = read_torrents(&path)?;
+            torrents.append(&mut inner);
+        }
+    }
+
+    return Ok(torrents);
+}
+
+/// Parse torrent files on blocking worker threads. The metadata of each file is sent as soon as it's
+/// parsed, so the files can be checked while the rest are still being parsed. The metadata of the
+/// `indexed` torrents is sent as is.
+fn spawn_parsers(paths: Vec<PathBuf>, indexed: Vec<TorrentMetadata>) -> mpsc::Receiver<TorrentMetadata> {
+    let workers = std::thread::available_parallelism().map_or(4, |workers| workers.get());
+    let chunk_size = paths.len() / workers + 1;
+
+    let (sender, receiver) = mpsc::channel(1024);
+
+    let indexed_sender = sender.clone();
+    tokio::spawn(async move {
+        for torrent in indexed {
+            if indexed_sender.send(torrent).await.is_err() {
+                break;
+            }
+        }
+    });
+    for chunk in paths.chunks(chunk_size) {
+        let chunk = chunk.to_vec();
+        let sender = sender.clone();
+
+        tokio::task::spawn_blocking(move || {
+            for path in chunk {
+                let torrent = match TorrentMetadata::read(path.clone()) {
+                    Ok(torrent) => torrent,
+                    Err(err) => {
+                        debug!("Failed to parse {}: {:?}", path.display(), err);
+                        continue;
+                    }
+                };
+
+                // The receiver is only dropped if parsing was cancelled.
+                if sender.blocking_send(torrent).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    receiver
+}
+
+/// The amount of torrents that are exported from the clients at the same time.
+const CONCURRENT_EXPORTS: usize = 8;
+
+/// Get the metadata of every torrent in the snapshots of the clients, sending each torrent as soon as
+/// it's exported. The torrents whose exported file is in `unchanged` aren't exported or parsed again.
+fn spawn_exporters(torrent_clients: Arc<TorrentClients>, export_path: PathBuf, unchanged: HashMap<PathBuf, TorrentMetadata>) -> mpsc::Receiver<TorrentMetadata> {
+    if let Err(err) = std::fs::create_dir_all(&export_path) {
+        error!("Failed to create the directory for exported torrents: {:?}", err);
+    }
+
+    let (sender, receiver) = mpsc::channel(1024);
+    tokio::spawn(async move {
+        let torrents = torrent_clients.clients().iter()
+            .flat_map(|client| client.snapshot_torrents().into_iter().map(move |info| (client, info)));
+
+        let (export_path, unchanged) = (&export_path, &unchanged);
+        let mut exported = futures::stream::iter(torrents)
+            .map(|(client, info)| async move {
+                match unchanged.get(&export_path.join(format!("{}.torrent", info.hash))) {
+                    Some(torrent) => Some(torrent.clone()),
+                    None => export_torrent(client, info, export_path).await,
+                }
+            })
+            .buffer_unordered(CONCURRENT_EXPORTS);
+
+        while let Some(torrent) = exported.next().await {
+            if let Some(torrent) = torrent {
+                if sender.send(torrent).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+/// Get the metadata of a torrent in a client. The `.torrent` file is only exported once, and is kept in
+/// `export_path` for the next runs. When the client can't export torrents, the files and trackers it
+/// reports for the torrent are used.
+async fn export_torrent(client: &TorrentClient, info: TorrentInfo, export_path: &Path) -> Option<TorrentMetadata> {
+    let path = export_path.join(format!("{}.torrent", info.hash));
+    if path.exists() {
+        match TorrentMetadata::read(path.clone()) {
+            Ok(torrent) => return Some(torrent),
+            Err(err) => debug!("Failed to parse exported torrent {}, exporting it again: {:?}", path.display(), err),
+        }
+    }
+
+    match fetch_torrent(client, &info, path).await {
+        Ok(torrent) => Some(torrent),
+        Err(err) => {
+            warn!("Failed to export {} from {}: {:?}", info.name, client.name(), err);
+            crate::metrics::METRICS.client_errors.inc();
+            None
+        }
+    }
+}
+
+/// Export a torrent from its client and save it to `path`, or read its metadata from the client if it
+/// can't export torrents.
+async fn fetch_torrent(client: &TorrentClient, info: &TorrentInfo, path: PathBuf) -> Result<TorrentMetadata, CrossSeedError> {
+    match client.export_torrent(info).await? {
+        Some(bytes) => {
+            let torrent = Torrent::read_from_bytes(&bytes)?;
+            tokio::fs::write(&path, &bytes).await.map_err(ClientError::from)?;
+
+            Ok(TorrentMetadata::new(path, &torrent))
+        },
+        None => {
+            let files = client.get_torrent_files(info).await?;
+            let trackers = client.get_torrent_trackers(info).await?;
+
+            Ok(TorrentMetadata::from_client(info, files, trackers))
+        },
+    }
+}
+
+async fn parse_torrents(config: &Config, torrent_clients: &Arc<TorrentClients>, mut unchanged: HashMap<PathBuf, TorrentMetadata>) -> Result<Vec<(LibraryEntry, TorrentMetadata)>, CrossSeedError> {
+    let metrics = &crate::metrics::METRICS;
+
+    // Parse the torrent files into their metadata, without keeping the full torrents.
+    let mut stop = stopwatch::Stopwatch::start_new();
+    let mut parsed = match config.torrents_path() {
+        Some(path) => {
+            // Read the torrents from the config as `PathBuf`s
+            let torrent_files = read_torrents(path)?;
+            info!("Found {} torrent files...", torrent_files.len());
+
+            let (mut indexed, mut changed) = (vec![], vec![]);
+            for path in torrent_files {
+                match unchanged.remove(&path) {
+                    Some(torrent) => indexed.push(torrent),
+                    None => changed.push(path),
+                }
+            }
+
+            info!("Parsing {} new or modified torrent files...", changed.len());
+            spawn_parsers(changed, indexed)
+        },
+        None => {
+            info!("Exporting the torrents of the torrent clients...");
+            spawn_exporters(Arc::clone(torrent_clients), config.exported_torrents_path(), unchanged)
+        },
+    };
+
+    // Remove the torrents that are not in any download client. The clients are looked
+    // up in their snapshots, so this doesn't make a request per torrent.
+    let mut eligible = vec![];
+    while let Some(torrent) = parsed.recv().await {
+        metrics.torrents_scanned.inc();
+
+        let info = match torrent_clients.find_torrent(&torrent.info_hash).await {
+            Ok(Some((_, info))) => info,
+            Ok(None) => continue,
+            Err(_) => {
+                metrics.client_errors.inc();
+                continue;
+            }
+        };
+
+        eligible.push((LibraryEntry::new(&torrent, Some(&info)), torrent));
+    }
+
+    stop.stop();
+    info!("Took {} seconds to parse and check all torrents", stop.elapsed().as_secs());
+
+    Ok(eligible)
+}
+
+/// Remove the torrents that are excluded by the filter rules. The rules only apply to searches, the library
+/// index keeps every local torrent so releases from rss and announces are still matched against them.
+async fn filter_torrents(seed: &CrossSeed, torrents: Vec<TorrentMetadata>) -> Vec<TorrentMetadata> {
+    let filter = match TorrentFilter::new(&seed.config().filters) {
+        Ok(filter) => filter,
+        Err(err) => {
+            error!("Invalid filter regex, not searching any torrents: {}", err);
+            return vec![];
+        }
+    };
+    let mut summary = FilterSummary::default();
+    let metrics = &crate::metrics::METRICS;
+
+    let mut eligible = vec![];
+    for torrent in torrents {
+        // The torrents were found in the snapshots of the clients when they were parsed.
+        let info = match seed.torrent_clients().find_torrent(&torrent.info_hash).await {
+            Ok(Some((_, info))) => info,
+            _ => continue,
+        };
+
+        if let Err(rule) = filter.check(&torrent, &info) {
+            debug!("Excluding {} by the {} rule", torrent.name, rule.as_str());
+            metrics.torrents_excluded.with_label_values(&[rule.as_str()]).inc();
+            summary.record(rule);
+            continue;
+        }
+
+        eligible.push(torrent);
+    }
+
+    if summary.total() > 0 {
+        info!("Excluded {} torrents by filter rules ({})", summary.total(), summary);
+    }
+
+    eligible
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use crate::torrent_client::{ClientResult, TorrentBackend, TorrentFile, TorrentState, TorrentUpload};
+
+    use super::*;
+
+    /// A single file torrent named `name`.
+    fn torrent_bytes(name: &str) -> Vec<u8> {
+        format!("d8:announce24:https://tracker/announce4:infod6:lengthi10e4:name{}:{}12:piece lengthi16384e6:pieces20:{}ee",
+            name.len(), name, "a".repeat(20)).into_bytes()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("crate-search-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sorted_names(torrents: Vec<TorrentMetadata>) -> Vec<String> {
+        let mut names: Vec<String> = torrents.into_iter().map(|torrent| torrent.name).collect();
+        names.sort();
+        names
+    }
+
+    async fn receive_all(mut receiver: mpsc::Receiver<TorrentMetadata>) -> Vec<TorrentMetadata> {
+        let mut torrents = vec![];
+        while let Some(torrent) = receiver.recv().await {
+            torrents.push(torrent);
+        }
+
+        torrents
+    }
+
+    /// A client whose even torrents can be exported, the odd ones only have their files and trackers.
+    #[derive(Default)]
+    struct ExportingBackend {
+        torrents: Vec<TorrentInfo>,
+        exported: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl TorrentBackend for Arc<ExportingBackend> {
+        async fn login(&mut self) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+            Ok(self.torrents.clone())
+        }
+
+        async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+            Ok(self.torrents.iter().find(|info| info.hash == hash).cloned())
+        }
+
+        async fn get_torrent_trackers(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+            Ok(vec![String::from("https://tracker/announce")])
+        }
+
+        async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+            Ok(vec![TorrentFile::from_content_path(&torrent.name, 10)])
+        }
+
+        async fn export_torrent(&self, torrent: &TorrentInfo) -> ClientResult<Option<Vec<u8>>> {
+            self.exported.lock().unwrap().push(torrent.name.clone());
+
+            let index: usize = torrent.name.trim_start_matches("torrent-").parse().unwrap();
+            Ok(Some(torrent_bytes(&torrent.name)).filter(|_| index % 2 == 0))
+        }
+
+        async fn get_torrent_progress(&self, _hash: &str) -> ClientResult<Option<f64>> {
+            Ok(Some(1.0))
+        }
+
+        async fn add_torrent_trackers(&self, _torrent: &TorrentInfo, _trackers: Vec<String>) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn recheck_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn resume_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn add_torrent(&self, _upload: &TorrentUpload) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn remove_torrent(&self, _torrent: &TorrentInfo, _delete_files: bool) -> ClientResult<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn parsers_send_every_torrent() {
+        let dir = temp_dir("parse");
+        let paths: Vec<PathBuf> = (0..50)
+            .map(|i| {
+                let path = dir.join(format!("{}.torrent", i));
+                std::fs::write(&path, torrent_bytes(&format!("torrent-{:02}", i))).unwrap();
+                path
+            })
+            .collect();
+        let broken = dir.join("broken.torrent");
+        std::fs::write(&broken, b"not a torrent").unwrap();
+
+        let indexed = TorrentMetadata::read(paths[0].clone()).unwrap();
+        let indexed = TorrentMetadata { name: String::from("indexed"), ..indexed };
+        let all_paths = paths[1..].iter().cloned().chain(Some(broken)).collect();
+
+        let torrents = receive_all(spawn_parsers(all_paths, vec![indexed])).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut expected: Vec<String> = (1..50).map(|i| format!("torrent-{:02}", i)).collect();
+        expected.push(String::from("indexed"));
+        expected.sort();
+        assert_eq!(sorted_names(torrents), expected);
+    }
+
+    #[tokio::test]
+    async fn exporters_send_every_torrent() {
+        let dir = temp_dir("export");
+        let torrents: Vec<TorrentInfo> = (0..20)
+            .map(|i| TorrentInfo {
+                name: format!("torrent-{:02}", i),
+                state: TorrentState::Uploading,
+                ..TorrentInfo::from_hash(format!("{:040x}", i))
+            })
+            .collect();
+
+        // The first torrent was exported in an earlier run and its file wasn't modified since.
+        let unchanged_path = dir.join(format!("{}.torrent", torrents[0].hash));
+        let unchanged = HashMap::from([(unchanged_path.clone(), TorrentMetadata {
+            path: Some(unchanged_path),
+            ..TorrentMetadata::from_client(&torrents[0], vec![], vec![])
+        })]);
+
+        let backend = Arc::new(ExportingBackend { torrents: torrents.clone(), ..ExportingBackend::default() });
+        let client = TorrentClient::new(String::from("stand-in"), Box::new(backend.clone()));
+        client.refresh_snapshot().await.unwrap();
+        let clients = Arc::new(TorrentClients::new(vec![client]).unwrap());
+
+        let received = receive_all(spawn_exporters(clients, dir.clone(), unchanged)).await;
+        let saved = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<String> = torrents.iter().map(|info| info.name.clone()).collect();
+        assert_eq!(sorted_names(received.clone()), names);
+        assert_eq!(backend.exported.lock().unwrap().len(), 19);
+        // Only the exported torrents are saved, the others come from the files and trackers of the client.
+        assert_eq!(saved, 9);
+        let from_client = received.iter().find(|torrent| torrent.name == "torrent-01").unwrap();
+        assert!(from_client.path.is_none());
+        assert_eq!(from_client.announce_list, vec![vec![String::from("https://tracker/announce")]]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use axum::extract::{Extension, Json};
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::cross_seed::MatchOutcome;
+use crate::torznab::TorrentResult;
+
+use super::ServerState;
+
+/// A release pushed by an announce channel tool, like autobrr.
+#[derive(Debug, Deserialize)]
+pub struct AnnounceRequest {
+    /// Name of the release.
+    pub name: String,
+    /// Total size of the release in bytes.
+    pub size: Option<u64>,
+    /// Name of the indexer the release was announced on.
+    pub indexer: String,
+    /// Url to download the `.torrent` file from.
+    pub link: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnounceResponse {
+    pub matched: bool,
+    pub message: String,
+}
+
+impl AnnounceResponse {
+    fn new(matched: bool, message: &str) -> Json<Self> {
+        Json(Self {
+            matched,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// `POST /api/announce`
+///
+/// Checks the release against the local library, and adds it as a cross-seed if it matches.
+/// Responds with `200` if it was added, `404` if it didn't match a local torrent and `409`
+/// if it's already in the client.
+pub async fn announce(Extension(state): Extension<Arc<ServerState>>, Json(req): Json<AnnounceRequest>) -> (StatusCode, Json<AnnounceResponse>) {
+    info!("Received announce of {} from {}", req.name, req.indexer);
+
+    let seed = &state.seed;
+
+    // Download the torrent through the client of the indexer so its proxy is used.
+    let indexer = seed.indexers().iter()
+        .find(|indexer| indexer.name.eq_ignore_ascii_case(&req.indexer));
+    let http = match indexer.and_then(|indexer| indexer.client.as_ref()) {
+        Some(client) => client.read().await.http().clone(),
+        None => match crate::util::http_client(seed.config().proxy.as_ref(), &seed.config().no_proxy) {
+            Ok(http) => http,
+            Err(err) => {
+                error!("Failed to create http client for announce: {:?}", err);
+                return (StatusCode::INTERNAL_SERVER_ERROR, AnnounceResponse::new(false, "failed to create http client"));
+            }
+        },
+    };
+
+    let release = TorrentResult {
+        name: req.name.clone(),
+        link: req.link.clone(),
+        size: req.size,
+    };
+
+    match seed.match_release(&req.indexer, &http, &release).await {
+        Ok(MatchOutcome::Added) => (StatusCode::OK, AnnounceResponse::new(true, "added cross-seed")),
+        Ok(MatchOutcome::WouldAdd) => (StatusCode::OK, AnnounceResponse::new(true, "would add cross-seed, dry run")),
+        Ok(MatchOutcome::AlreadySeeding) => (StatusCode::CONFLICT, AnnounceResponse::new(true, "already seeding")),
+        Ok(MatchOutcome::NoMatch) => (StatusCode::NOT_FOUND, AnnounceResponse::new(false, "no matching torrent")),
+        Ok(MatchOutcome::NotAdded) => (StatusCode::ACCEPTED, AnnounceResponse::new(true, "matched, but the cross-seed couldn't be added yet")),
+        Err(err) => {
+            error!("Failed to check announced release {}: {:?}", req.name, err);
+            (StatusCode::INTERNAL_SERVER_ERROR, AnnounceResponse::new(false, "failed to check release"))
+        }
+    }
+}
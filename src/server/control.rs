@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use axum::extract::{Extension, Json, Query};
+use axum::http::{header, HeaderName, StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::daemon::SearchRun;
+use crate::database::{Decision, DecisionQuery, DecisionRecord};
+use crate::health::Health;
+
+use super::ServerState;
+
+/// The amount of decisions returned when the request doesn't have a limit.
+const DEFAULT_LIMIT: u32 = 100;
+
+#[derive(Debug, Serialize)]
+pub struct MessageResponse {
+    pub message: String,
+}
+
+impl MessageResponse {
+    fn new(message: &str) -> Json<Self> {
+        Json(Self {
+            message: message.to_string(),
+        })
+    }
+}
+
+/// `POST /api/search`
+///
+/// Starts a full library search in the background. Responds with `202` if the search
+/// was started and `409` if one is already running.
+pub async fn search(Extension(state): Extension<Arc<ServerState>>) -> (StatusCode, Json<MessageResponse>) {
+    if state.daemon.is_searching() {
+        return (StatusCode::CONFLICT, MessageResponse::new("a library search is already running"));
+    }
+
+    info!("Library search requested over http");
+
+    let daemon = Arc::clone(&state.daemon);
+    tokio::spawn(async move { daemon.search_library().await });
+
+    (StatusCode::ACCEPTED, MessageResponse::new("library search started"))
+}
+
+/// A single local torrent to search for, by its info hash or its name.
+#[derive(Debug, Deserialize)]
+pub struct SearchTorrentRequest {
+    pub infohash: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchTorrentResponse {
+    /// Info hashes of the torrents that were queued.
+    pub queued: Vec<String>,
+}
+
+/// `POST /api/search/torrent`
+///
+/// Queues a search for a local torrent. When searching by name, every library torrent
+/// with the same name is queued. Responds with `202` if any torrent was queued, and `404`
+/// if no torrent with the name is in the library.
+pub async fn search_torrent(Extension(state): Extension<Arc<ServerState>>, Json(req): Json<SearchTorrentRequest>) -> (StatusCode, Json<SearchTorrentResponse>) {
+    let queued: Vec<String> = match (req.infohash, req.name) {
+        (Some(info_hash), _) => vec![info_hash.trim().to_lowercase()],
+        (None, Some(name)) => state.seed.library().read().await
+            .find_by_name(&name)
+            .into_iter()
+            .map(|entry| entry.info_hash.clone())
+            .collect(),
+        (None, None) => return (StatusCode::BAD_REQUEST, Json(SearchTorrentResponse { queued: vec![] })),
+    };
+
+    if queued.is_empty() {
+        return (StatusCode::NOT_FOUND, Json(SearchTorrentResponse { queued }));
+    }
+
+    for info_hash in queued.iter() {
+        info!("Search for {} requested over http", info_hash);
+        state.daemon.queue_search(info_hash.clone());
+    }
+
+    (StatusCode::ACCEPTED, Json(SearchTorrentResponse { queued }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    pub search_running: bool,
+    pub last_search: Option<SearchRun>,
+    /// The amount of single torrents waiting to be searched for.
+    pub queue_depth: usize,
+    pub library_size: usize,
+    /// Health of every torrent client.
+    pub clients: Vec<ClientStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientStatus {
+    pub name: String,
+    pub healthy: bool,
+    #[serde(flatten)]
+    pub health: Health,
+}
+
+/// `GET /api/status`
+pub async fn status(Extension(state): Extension<Arc<ServerState>>) -> Json<StatusResponse> {
+    Json(StatusResponse {
+        search_running: state.daemon.is_searching(),
+        last_search: state.daemon.last_search(),
+        queue_depth: state.daemon.queue_depth(),
+        library_size: state.seed.library().read().await.len(),
+        clients: state.seed.torrent_clients().clients().iter()
+            .map(|client| {
+                let health = state.seed.health().client(client.name());
+
+                ClientStatus {
+                    name: client.name().to_string(),
+                    healthy: health.is_healthy(),
+                    health,
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Filters of the decision log, see `DecisionQuery`.
+#[derive(Debug, Deserialize)]
+pub struct DecisionsParams {
+    pub name: Option<String>,
+    pub infohash: Option<String>,
+    pub indexer: Option<String>,
+    pub decision: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// `GET /api/decisions`
+///
+/// Lists the most recent decisions, filtered by the `name`, `infohash`, `indexer`
+/// and `decision` query parameters.
+pub async fn decisions(Extension(state): Extension<Arc<ServerState>>, Query(params): Query<DecisionsParams>) -> Result<Json<Vec<DecisionRecord>>, StatusCode> {
+    let decision = match params.decision {
+        Some(decision) => Some(Decision::parse(&decision).ok_or(StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+
+    query_decisions(&state, DecisionQuery {
+        name: params.name,
+        info_hash: params.infohash,
+        indexer: params.indexer,
+        decision,
+        limit: Some(params.limit.unwrap_or(DEFAULT_LIMIT)),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InjectionsParams {
+    pub limit: Option<u32>,
+}
+
+/// `GET /api/injections`
+///
+/// Lists the most recently injected cross-seeds.
+pub async fn injections(Extension(state): Extension<Arc<ServerState>>, Query(params): Query<InjectionsParams>) -> Result<Json<Vec<DecisionRecord>>, StatusCode> {
+    query_decisions(&state, DecisionQuery {
+        decision: Some(Decision::Injected),
+        limit: Some(params.limit.unwrap_or(DEFAULT_LIMIT)),
+        ..Default::default()
+    })
+}
+
+fn query_decisions(state: &ServerState, query: DecisionQuery) -> Result<Json<Vec<DecisionRecord>>, StatusCode> {
+    let db = state.seed.database().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    match db.query_decisions(&query) {
+        Ok(decisions) => Ok(Json(decisions)),
+        Err(err) => {
+            error!("Failed to query decisions: {:?}", err);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexerStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub healthy: bool,
+    #[serde(flatten)]
+    pub health: Health,
+}
+
+/// `GET /api/indexers`
+///
+/// Reports the health of every indexer, based on the requests made to it since startup.
+pub async fn indexers(Extension(state): Extension<Arc<ServerState>>) -> Json<Vec<IndexerStatus>> {
+    let indexers = state.seed.indexers().iter()
+        .map(|indexer| {
+            let health = state.seed.health().get(&indexer.name);
+
+            IndexerStatus {
+                name: indexer.name.clone(),
+                enabled: indexer.enabled,
+                healthy: health.is_healthy(),
+                health,
+            }
+        })
+        .collect();
+
+    Json(indexers)
+}
+
+/// `GET /metrics`
+///
+/// Exposes the metrics in the prometheus text format.
+pub async fn metrics() -> ([(HeaderName, &'static str); 1], String) {
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], crate::metrics::METRICS.encode())
+}
@@ -0,0 +1,131 @@
+pub mod announce;
+pub mod control;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{Router, routing::{get, post}};
+use axum::extract::Extension;
+use axum::http::{Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use tokio::sync::watch;
+use tracing::{error, info};
+
+use crate::config::ServerConfig;
+use crate::cross_seed::CrossSeed;
+use crate::daemon::Daemon;
+
+/// State shared with the request handlers.
+pub struct ServerState {
+    pub config: ServerConfig,
+    pub seed: Arc<CrossSeed>,
+    pub daemon: Arc<Daemon>,
+}
+
+/// Run the http server until it fails, or until `shutdown` changes.
+pub async fn serve(config: ServerConfig, daemon: Arc<Daemon>, mut shutdown: watch::Receiver<bool>) {
+    let addr: SocketAddr = match config.bind_address.parse() {
+        Ok(addr) => addr,
+        Err(_) => {
+            error!("Invalid server bind address: {}", config.bind_address);
+            return;
+        }
+    };
+
+    // Without a key anyone who can reach the server could make it download urls and add torrents.
+    if config.api_key.trim().is_empty() {
+        error!("The server's api_key is empty, not starting it");
+        return;
+    }
+
+    let state = Arc::new(ServerState {
+        config,
+        seed: Arc::clone(daemon.seed()),
+        daemon,
+    });
+
+    // The extension layer is added last so the state is available to the auth middleware.
+    let app = Router::new()
+        .route("/api/announce", post(announce::announce))
+        .route("/api/search", post(control::search))
+        .route("/api/search/torrent", post(control::search_torrent))
+        .route("/api/status", get(control::status))
+        .route("/api/decisions", get(control::decisions))
+        .route("/api/injections", get(control::injections))
+        .route("/api/indexers", get(control::indexers))
+        .route("/metrics", get(control::metrics))
+        .layer(middleware::from_fn(authenticate))
+        .layer(Extension(state));
+
+    info!("Listening for http requests on {}", addr);
+    let server = axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async move {
+            shutdown.changed().await.ok();
+        });
+
+    if let Err(err) = server.await {
+        error!("Http server failed: {}", err);
+    }
+}
+
+/// Reject requests that don't have the api key of the server.
+async fn authenticate<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    let authorized = {
+        let state = req.extensions().get::<Arc<ServerState>>().unwrap();
+        let api_key = &state.config.api_key;
+
+        let header_key = req.headers().get("X-Api-Key")
+            .and_then(|value| value.to_str().ok());
+
+        header_key.map_or(false, |key| keys_match(key, api_key))
+            || query_key(req.uri().query()).map_or(false, |key| keys_match(&key, api_key))
+    };
+
+    if authorized {
+        Ok(next.run(req).await)
+    } else {
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// Get the url decoded `apikey` parameter of a query string.
+fn query_key(query: Option<&str>) -> Option<String> {
+    query?.split('&')
+        .find_map(|pair| pair.strip_prefix("apikey="))
+        .and_then(|key| urlencoding::decode(key).ok())
+        .map(|key| key.into_owned())
+}
+
+/// Compare an api key in constant time, so the key can't be guessed from how long a comparison takes.
+fn keys_match(key: &str, expected: &str) -> bool {
+    if key.len() != expected.len() {
+        return false;
+    }
+
+    key.bytes()
+        .zip(expected.bytes())
+        .fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_match_only_equal_keys() {
+        assert!(keys_match("secret", "secret"));
+        assert!(!keys_match("secreT", "secret"));
+        assert!(!keys_match("secret1", "secret"));
+        assert!(!keys_match("", "secret"));
+    }
+
+    #[test]
+    fn query_key_is_url_decoded() {
+        assert_eq!(query_key(Some("a=1&apikey=a%2Bb%3D")), Some(String::from("a+b=")));
+        assert_eq!(query_key(Some("apikey=plain")), Some(String::from("plain")));
+        assert_eq!(query_key(Some("key=plain")), None);
+        assert_eq!(query_key(None), None);
+    }
+}
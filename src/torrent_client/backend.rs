@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use tracing::debug;
+
+use super::{ClientResult, TorrentFile, TorrentInfo, TorrentUpload};
+
+/// The requests cross-seed makes to a torrent client. Each supported client implements this.
+#[async_trait]
+pub trait TorrentBackend {
+    async fn login(&mut self) -> ClientResult<()>;
+
+    /// Get every torrent in the client.
+    async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>>;
+
+    /// Get a torrent by its info hash.
+    async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>>;
+
+    /// Get the announce urls of a torrent.
+    async fn get_torrent_trackers(&self, torrent: &TorrentInfo) -> ClientResult<Vec<String>>;
+
+    /// Get the files of a torrent with their sizes.
+    async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>>;
+
+    /// Export the `.torrent` file of a torrent. Returns `None` if the client can't export torrents.
+    async fn export_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Get the progress of a torrent's download, from `0.0` to `1.0`. Returns `None` if the torrent isn't in the client.
+    async fn get_torrent_progress(&self, hash: &str) -> ClientResult<Option<f64>>;
+
+    async fn add_torrent_trackers(&self, torrent: &TorrentInfo, trackers: Vec<String>) -> ClientResult<()>;
+
+    /// Whether the client has tags. Clients without tags report none for their torrents, and ignore the
+    /// tags of added torrents.
+    fn supports_tags(&self) -> bool {
+        false
+    }
+
+    /// Add tags to a torrent, keeping its existing tags. Clients without tags ignore this.
+    async fn add_torrent_tags(&self, torrent: &TorrentInfo, tags: Vec<String>) -> ClientResult<()> {
+        debug!("Client doesn't support tags, not adding {:?} to {}", tags, torrent.hash);
+        Ok(())
+    }
+
+    /// Start a recheck of a torrent's data.
+    async fn recheck_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()>;
+
+    /// Start a paused torrent.
+    async fn resume_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()>;
+
+    async fn add_torrent(&self, upload: &TorrentUpload) -> ClientResult<()>;
+
+    async fn remove_torrent(&self, torrent: &TorrentInfo, delete_files: bool) -> ClientResult<()>;
+}
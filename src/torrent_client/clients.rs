@@ -0,0 +1,79 @@
+use tracing::info;
+
+use crate::config::Config;
+
+use super::{ClientError, ClientResult, TorrentClient, TorrentInfo};
+
+/// Every torrent client in the config. Local torrents are resolved to the client that holds them,
+/// and checks for existing torrents span all of the clients.
+pub struct TorrentClients {
+    clients: Vec<TorrentClient>,
+}
+
+impl TorrentClients {
+    /// Group the clients, there has to be at least one.
+    pub fn new(clients: Vec<TorrentClient>) -> ClientResult<Self> {
+        if clients.is_empty() {
+            return Err(ClientError::NoClients);
+        }
+
+        Ok(Self {
+            clients,
+        })
+    }
+
+    pub fn from_config(config: &Config) -> ClientResult<Self> {
+        let clients = config.torrent_clients().iter()
+            .map(|client| TorrentClient::from_config(client, config.proxy.as_ref(), &config.no_proxy))
+            .collect::<ClientResult<Vec<TorrentClient>>>()?;
+
+        Self::new(clients)
+    }
+
+    pub fn clients(&self) -> &[TorrentClient] {
+        &self.clients
+    }
+
+    pub async fn login(&mut self) -> ClientResult<()> {
+        for client in self.clients.iter_mut() {
+            client.login().await?;
+            info!("Logged in to torrent client {}", client.name());
+        }
+
+        Ok(())
+    }
+
+    /// Find the client that holds a torrent by its info hash, with the torrent's info in that client.
+    pub async fn find_torrent(&self, hash: &str) -> ClientResult<Option<(&TorrentClient, TorrentInfo)>> {
+        for client in self.clients.iter() {
+            if let Some(info) = client.get_torrent_info(hash).await? {
+                return Ok(Some((client, info)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Checks if any client has the torrent with the exact hash, so a cross-seed isn't added to one client
+    /// while another already seeds it.
+    pub async fn has_exact_torrent(&self, hash: &str) -> ClientResult<bool> {
+        for client in self.clients.iter() {
+            if client.has_exact_torrent(hash).await? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Take a new snapshot of every client with one torrent list request each, returning the torrents of
+    /// every client. Lookups are answered from the snapshots afterwards.
+    pub async fn refresh(&self) -> ClientResult<Vec<TorrentInfo>> {
+        let mut torrents = vec![];
+        for client in self.clients.iter() {
+            torrents.extend(client.refresh_snapshot().await?);
+        }
+
+        Ok(torrents)
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, info};
+
+use crate::config::client::deluge::DelugeConfig;
+
+use super::{ClientError, ClientResult, ContentLayout, TorrentBackend, TorrentFile, TorrentInfo, TorrentState, TorrentUpload};
+
+/// The error message of the web ui when its session expired or it was never logged in to.
+const NOT_AUTHENTICATED: &str = "Not authenticated";
+
+/// The torrent fields requested from deluge.
+const TORRENT_FIELDS: [&str; 7] = ["name", "state", "progress", "label", "save_path", "trackers", "time_added"];
+
+/// A deluge client, using the json rpc of its web ui. The label plugin is used for categories.
+pub struct DelugeBackend {
+    /// The session cookie of the web ui is kept by the cookie store of the client.
+    http: reqwest::Client,
+    config: DelugeConfig,
+    request_id: AtomicU64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: Value,
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DelugeTorrent {
+    name: String,
+    state: String,
+    progress: f64,
+    #[serde(default)]
+    label: String,
+    save_path: Option<String>,
+    #[serde(default)]
+    trackers: Vec<DelugeTracker>,
+    /// Unix timestamp with fractional seconds.
+    #[serde(default)]
+    time_added: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct DelugeTracker {
+    url: String,
+    tier: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DelugeFile {
+    path: String,
+    size: u64,
+}
+
+impl DelugeTorrent {
+    /// Map the state of the torrent to a `TorrentState`.
+    fn torrent_state(&self) -> TorrentState {
+        let done = self.progress >= 100.0;
+
+        match self.state.as_str() {
+            "Seeding" => TorrentState::Uploading,
+            "Downloading" => TorrentState::Downloading,
+            "Paused" if done => TorrentState::PausedUploading,
+            "Paused" => TorrentState::PausedDownloading,
+            "Queued" if done => TorrentState::QueuedUploading,
+            "Queued" => TorrentState::QueuedDownloading,
+            "Checking" if done => TorrentState::CheckingUploading,
+            "Checking" => TorrentState::CheckingDownloading,
+            "Allocating" => TorrentState::Allocating,
+            "Moving" => TorrentState::Moving,
+            "Error" => TorrentState::Error,
+            _ => TorrentState::Unknown,
+        }
+    }
+
+    fn into_info(self, hash: String) -> TorrentInfo {
+        TorrentInfo {
+            state: self.torrent_state(),
+            hash: hash.to_lowercase(),
+            name: self.name,
+            category: self.label,
+            // Deluge doesn't have tags, the label plugin only gives a torrent one label.
+            tags: vec![],
+            save_path: self.save_path,
+            added_on: Some(self.time_added as i64).filter(|added| *added > 0),
+        }
+    }
+}
+
+impl DelugeBackend {
+    pub fn new(config: DelugeConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            config,
+            request_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Call an rpc method of the web ui and return its result.
+    ///
+    /// When the session of the web ui expired, this logs in again and retries the call once.
+    async fn call(&self, method: &str, params: Value) -> ClientResult<Value> {
+        match self.call_once(method, params.clone()).await {
+            Err(ClientError::Rpc(message)) if message == NOT_AUTHENTICATED => {
+                debug!("Deluge session expired, logging in again...");
+                self.authenticate().await?;
+
+                self.call_once(method, params).await
+            },
+            result => result,
+        }
+    }
+
+    /// Call an rpc method of the web ui once, without logging in again.
+    async fn call_once(&self, method: &str, params: Value) -> ClientResult<Value> {
+        let url = format!("{}/json", self.config.url.trim_end_matches('/'));
+        let body = json!({
+            "method": method,
+            "params": params,
+            "id": self.request_id.fetch_add(1, Ordering::SeqCst),
+        });
+
+        let res: RpcResponse = self.http.post(url)
+            .json(&body)
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+
+        match res.error {
+            Some(error) => Err(ClientError::Rpc(error.message)),
+            None => Ok(res.result),
+        }
+    }
+
+    /// Log in to the web ui and connect it to a daemon, the session cookie is kept by the cookie store.
+    async fn authenticate(&self) -> ClientResult<()> {
+        let authenticated = self.call_once("auth.login", json!([self.config.password])).await?;
+        if authenticated.as_bool() != Some(true) {
+            return Err(ClientError::Unauthorized);
+        }
+
+        self.connect().await
+    }
+
+    /// Connect the web ui to a daemon if it isn't connected to one. This is only done right after logging in,
+    /// so the calls aren't retried.
+    async fn connect(&self) -> ClientResult<()> {
+        if self.call_once("web.connected", json!([])).await?.as_bool() == Some(true) {
+            return Ok(());
+        }
+
+        // Hosts are returned as `[id, host, port, status]`.
+        let hosts = self.call_once("web.get_hosts", json!([])).await?;
+        let host_id = match &self.config.host {
+            Some(host) => Some(host.clone()),
+            None => hosts.get(0)
+                .and_then(|host| host.get(0))
+                .and_then(|id| id.as_str())
+                .map(|id| id.to_string()),
+        };
+        let host_id = host_id.ok_or_else(|| ClientError::Rpc(String::from("deluge web ui has no daemon hosts")))?;
+
+        info!("Connecting deluge web ui to daemon {}...", host_id);
+        self.call_once("web.connect", json!([host_id])).await?;
+
+        Ok(())
+    }
+
+    async fn get_deluge_torrents(&self, filter: Value) -> ClientResult<HashMap<String, DelugeTorrent>> {
+        let res = self.call("core.get_torrents_status", json!([filter, TORRENT_FIELDS])).await?;
+        Ok(serde_json::from_value(res)?)
+    }
+
+    /// Set the label of a torrent, creating the label if it doesn't exist.
+    async fn set_label(&self, hash: &str, label: &str) -> ClientResult<()> {
+        // The label plugin only allows lowercase labels.
+        let label = label.to_lowercase();
+
+        let labels: Vec<String> = serde_json::from_value(self.call("label.get_labels", json!([])).await?)?;
+        if !labels.contains(&label) {
+            self.call("label.add", json!([label])).await?;
+        }
+
+        self.call("label.set_torrent", json!([hash, label])).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TorrentBackend for DelugeBackend {
+    async fn login(&mut self) -> ClientResult<()> {
+        self.authenticate().await
+    }
+
+    async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+        let torrents = self.get_deluge_torrents(json!({})).await?;
+
+        Ok(torrents.into_iter()
+            .map(|(hash, torrent)| torrent.into_info(hash))
+            .collect())
+    }
+
+    async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+        let torrents = self.get_deluge_torrents(json!({ "id": [hash.to_lowercase()] })).await?;
+
+        Ok(torrents.into_iter()
+            .next()
+            .map(|(hash, torrent)| torrent.into_info(hash)))
+    }
+
+    async fn get_torrent_trackers(&self, torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+        let torrents = self.get_deluge_torrents(json!({ "id": [torrent.hash] })).await?;
+
+        Ok(torrents.into_values()
+            .flat_map(|torrent| torrent.trackers)
+            .map(|tracker| tracker.url)
+            .collect())
+    }
+
+    async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+        let res = self.call("core.get_torrent_status", json!([torrent.hash, ["files"]])).await?;
+
+        let files: Vec<DelugeFile> = serde_json::from_value(res["files"].clone())?;
+        Ok(files.into_iter()
+            .map(|file| TorrentFile::from_content_path(&file.path, file.size))
+            .collect())
+    }
+
+    async fn get_torrent_progress(&self, hash: &str) -> ClientResult<Option<f64>> {
+        let torrents = self.get_deluge_torrents(json!({ "id": [hash.to_lowercase()] })).await?;
+
+        // Deluge reports progress as a percentage.
+        Ok(torrents.into_values()
+            .next()
+            .map(|torrent| torrent.progress / 100.0))
+    }
+
+    async fn add_torrent_trackers(&self, torrent: &TorrentInfo, trackers: Vec<String>) -> ClientResult<()> {
+        // Deluge can only replace the whole tracker list, so the new trackers are added as new tiers after the existing ones.
+        let mut current: Vec<DelugeTracker> = self.get_deluge_torrents(json!({ "id": [torrent.hash] })).await?
+            .into_values()
+            .flat_map(|torrent| torrent.trackers)
+            .collect();
+
+        let mut tier = current.iter().map(|tracker| tracker.tier + 1).max().unwrap_or(0);
+        for url in trackers {
+            if !current.iter().any(|tracker| tracker.url == url) {
+                current.push(DelugeTracker { url, tier });
+                tier += 1;
+            }
+        }
+
+        self.call("core.set_torrent_trackers", json!([torrent.hash, current])).await?;
+        Ok(())
+    }
+
+    async fn recheck_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.call("core.force_recheck", json!([[torrent.hash]])).await?;
+        Ok(())
+    }
+
+    async fn resume_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.call("core.resume_torrent", json!([[torrent.hash]])).await?;
+        Ok(())
+    }
+
+    async fn add_torrent(&self, upload: &TorrentUpload) -> ClientResult<()> {
+        let mut options = json!({ "add_paused": upload.paused });
+        if let Some(save_path) = &upload.save_path {
+            options["download_location"] = json!(save_path);
+        }
+
+        let hash = self.call("core.add_torrent_file", json!([upload.filename, base64::encode(&upload.data), options])).await?;
+        let hash = hash.as_str()
+            .ok_or_else(|| ClientError::Rpc(format!("deluge didn't add {}, it may already be added", upload.filename)))?;
+
+        if let Some(category) = &upload.category {
+            self.set_label(hash, category).await?;
+        }
+
+        match &upload.content_layout {
+            ContentLayout::Original => {},
+            ContentLayout::Renamed(root) => {
+                let torrent = upload.read_torrent()?;
+                self.call("core.rename_folder", json!([hash, format!("{}/", torrent.name), format!("{}/", root)])).await?;
+            },
+            ContentLayout::NoSubfolder => {
+                // Deluge's file paths include the folder, so the files are moved out of it by their index.
+                let torrent = upload.read_torrent()?;
+                let renames: Vec<(usize, String)> = torrent.files.iter()
+                    .flatten()
+                    .map(|file| file.path.to_string_lossy().to_string())
+                    .enumerate()
+                    .collect();
+
+                self.call("core.rename_files", json!([hash, renames])).await?;
+            },
+        }
+
+        if !upload.tags.is_empty() {
+            debug!("Deluge doesn't support tags, not adding {:?} to {}", upload.tags, upload.filename);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_torrent(&self, torrent: &TorrentInfo, delete_files: bool) -> ClientResult<()> {
+        self.call("core.remove_torrent", json!([torrent.hash, delete_files])).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{routing::post, Json, Router};
+
+    use super::*;
+
+    fn torrent(state: &str, progress: f64) -> DelugeTorrent {
+        DelugeTorrent {
+            name: String::from("Name"),
+            state: state.to_string(),
+            progress,
+            label: String::from("movies"),
+            save_path: Some(String::from("/downloads")),
+            trackers: vec![],
+            time_added: 1_700_000_000.5,
+        }
+    }
+
+    #[test]
+    fn maps_states() {
+        assert!(matches!(torrent("Seeding", 100.0).torrent_state(), TorrentState::Uploading));
+        assert!(matches!(torrent("Downloading", 50.0).torrent_state(), TorrentState::Downloading));
+        assert!(matches!(torrent("Paused", 100.0).torrent_state(), TorrentState::PausedUploading));
+        assert!(matches!(torrent("Paused", 50.0).torrent_state(), TorrentState::PausedDownloading));
+        assert!(matches!(torrent("Queued", 100.0).torrent_state(), TorrentState::QueuedUploading));
+        assert!(matches!(torrent("Queued", 50.0).torrent_state(), TorrentState::QueuedDownloading));
+        assert!(matches!(torrent("Checking", 100.0).torrent_state(), TorrentState::CheckingUploading));
+        assert!(matches!(torrent("Checking", 50.0).torrent_state(), TorrentState::CheckingDownloading));
+        assert!(matches!(torrent("Allocating", 0.0).torrent_state(), TorrentState::Allocating));
+        assert!(matches!(torrent("Moving", 100.0).torrent_state(), TorrentState::Moving));
+        assert!(matches!(torrent("Error", 100.0).torrent_state(), TorrentState::Error));
+        assert!(matches!(torrent("Something", 100.0).torrent_state(), TorrentState::Unknown));
+    }
+
+    #[test]
+    fn label_is_the_category() {
+        let info = torrent("Seeding", 100.0).into_info(String::from("ABCDEF"));
+
+        assert_eq!(info.hash, "abcdef");
+        assert_eq!(info.category, "movies");
+        assert!(info.tags.is_empty());
+        assert_eq!(info.added_on, Some(1_700_000_000));
+    }
+
+    #[tokio::test]
+    async fn logs_in_again_when_session_expired() {
+        // The web ui only answers after a login, like a session that expired since the last run.
+        let logins = Arc::new(AtomicUsize::new(0));
+        let login_count = Arc::clone(&logins);
+        let app = Router::new().route("/json", post(move |Json(request): Json<Value>| {
+            let logins = Arc::clone(&login_count);
+            async move {
+                let response = match (request["method"].as_str(), logins.load(Ordering::SeqCst)) {
+                    (Some("auth.login"), _) => {
+                        logins.fetch_add(1, Ordering::SeqCst);
+                        json!({ "result": true, "error": null })
+                    },
+                    (Some("web.connected"), _) => json!({ "result": true, "error": null }),
+                    (_, 0) => json!({ "result": null, "error": { "message": NOT_AUTHENTICATED, "code": 1 } }),
+                    _ => json!({ "result": {}, "error": null }),
+                };
+
+                Json(response)
+            }
+        }));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        let config = DelugeConfig { url, password: String::from("deluge"), host: None };
+        let backend = DelugeBackend::new(config, reqwest::Client::new());
+
+        assert!(backend.get_torrents().await.unwrap().is_empty());
+        assert_eq!(logins.load(Ordering::SeqCst), 1);
+    }
+}
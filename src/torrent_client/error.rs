@@ -0,0 +1,50 @@
+pub type ClientResult<T> = Result<T, ClientError>;
+
+#[derive(Debug)]
+pub enum ClientError {
+    HttpError(reqwest::Error),
+    /// The proxy of the client's http requests is invalid.
+    ProxyError(crate::config::ProxyError),
+    SerdeError(serde_json::Error),
+    /// The client rejected the login credentials.
+    Unauthorized,
+    /// The config doesn't list any torrent clients.
+    NoClients,
+    /// The client responded to a request with an error.
+    Rpc(String),
+    /// The client responded with xml-rpc that couldn't be parsed.
+    XmlRpc(String),
+    IoError(std::io::Error),
+    /// The torrent file being added couldn't be read.
+    TorrentError(lava_torrent::LavaTorrentError),
+}
+
+impl From<crate::config::ProxyError> for ClientError {
+    fn from(e: crate::config::ProxyError) -> Self {
+        ClientError::ProxyError(e)
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::HttpError(e)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::SerdeError(e)
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(e: std::io::Error) -> Self {
+        ClientError::IoError(e)
+    }
+}
+
+impl From<lava_torrent::LavaTorrentError> for ClientError {
+    fn from(e: lava_torrent::LavaTorrentError) -> Self {
+        ClientError::TorrentError(e)
+    }
+}
@@ -1,67 +1,226 @@
-use std::ops::{Deref, DerefMut};
+pub mod backend;
+pub use backend::*;
 
-use abstracttorrent::{client::qbittorrent, torrent::TorrentInfo, common::GetTorrentListParams};
-use lava_torrent::torrent::v1::Torrent;
+pub mod error;
+pub use error::*;
 
-use crate::config::Config;
+pub mod torrent;
+pub use torrent::*;
+
+pub mod clients;
+pub use clients::*;
+
+pub mod snapshot;
+pub use snapshot::*;
+
+pub mod deluge;
+pub mod qbittorrent;
+pub mod rtorrent;
+pub mod transmission;
+pub mod xmlrpc;
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use tracing::debug;
+
+use crate::config::ProxyConfig;
+use crate::config::client::{ClientConfig, ClientKind, PathMapping};
 
 pub struct TorrentClient {
-    client: Box<dyn abstracttorrent::client::TorrentClient + Send + Sync>,
+    name: String,
+    client: Box<dyn TorrentBackend + Send + Sync>,
+    /// The torrents in the client at the last refresh. Lookups are answered from this instead of
+    /// the client once it's taken.
+    snapshot: RwLock<Option<ClientSnapshot>>,
+    path_mappings: Vec<PathMapping>,
 }
 
 impl TorrentClient {
-    pub fn from_config(config: &Config) -> Self {
-        // TODO: figure out which client to use if multiple are specified.
-
-        if let Some(qbittorrent) = &config.qbittorrent {
-            TorrentClient {
-                client: Box::new(qbittorrent::client::QBittorrentClient::new())
-            }
-        } else {
-            panic!("Invalid config!");
+    /// Create a client named `name` that makes its requests with `client`, without path mappings.
+    pub fn new(name: String, client: Box<dyn TorrentBackend + Send + Sync>) -> Self {
+        TorrentClient {
+            name,
+            client,
+            snapshot: RwLock::new(None),
+            path_mappings: vec![],
         }
     }
 
-    pub async fn login(&mut self, config: &Config) -> abstracttorrent::client::ClientResult<()> {
-        let (url, username, password) = match &config.qbittorrent {
-            Some(qb) => {
-                (&qb.url, &qb.username, &qb.password)
-            },
-            None => {
-                panic!("Invalid config!");
-            }
+    /// Create the client of a config. If the client opted in with `proxy`, its requests are routed through the proxy,
+    /// unless its host is in `no_proxy`.
+    pub fn from_config(config: &ClientConfig, proxy: Option<&ProxyConfig>, no_proxy: &[String]) -> ClientResult<Self> {
+        let proxy = proxy.filter(|_| config.proxy);
+
+        // The cookie store keeps the sessions of the clients that log in with cookies.
+        let http = crate::util::http_client_builder(proxy, no_proxy)?
+            .cookie_store(true)
+            .build()?;
+
+        let client: Box<dyn TorrentBackend + Send + Sync> = match &config.kind {
+            ClientKind::QBittorrent(qbittorrent) => Box::new(qbittorrent::QBittorrentBackend::new(qbittorrent.clone(), http)),
+            ClientKind::Transmission(transmission) => Box::new(transmission::TransmissionBackend::new(transmission.clone(), http)),
+            ClientKind::Deluge(deluge) => Box::new(deluge::DelugeBackend::new(deluge.clone(), http)),
+            ClientKind::RTorrent(rtorrent) => Box::new(rtorrent::RTorrentBackend::new(rtorrent.clone(), http)),
         };
 
-        self.client.login(&url, username, password).await
+        Ok(TorrentClient {
+            path_mappings: config.path_mappings.clone(),
+            ..Self::new(config.name.clone(), client)
+        })
+    }
+
+    /// The name of the client from the config.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn supports_tags(&self) -> bool {
+        self.client.supports_tags()
+    }
+
+    /// Translate a path the client reports to the path cross-seed sees with the first mapping that applies.
+    /// Returns `None` if the client has no path mappings, since its paths may not be visible to cross-seed.
+    pub fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        if self.path_mappings.is_empty() {
+            return None;
+        }
+
+        let local = self.path_mappings.iter()
+            .find_map(|mapping| mapping.to_local(path))
+            .unwrap_or_else(|| path.to_path_buf());
+        Some(local)
+    }
+
+    pub async fn login(&mut self) -> ClientResult<()> {
+        self.client.login().await
+    }
+
+    /// Gets every torrent in the client.
+    pub async fn get_torrent_list(&self) -> ClientResult<Vec<TorrentInfo>> {
+        self.client.get_torrents().await
+    }
+
+    /// Take a new snapshot of the torrents in the client, returning the torrents.
+    pub async fn refresh_snapshot(&self) -> ClientResult<Vec<TorrentInfo>> {
+        let torrents = self.get_torrent_list().await?;
+        debug!("Took a snapshot of {} torrents in {}", torrents.len(), self.name);
+
+        *self.snapshot.write().unwrap() = Some(ClientSnapshot::new(torrents.clone()));
+        Ok(torrents)
+    }
+
+    /// The torrents in the snapshot, empty if no snapshot was taken yet.
+    pub fn snapshot_torrents(&self) -> Vec<TorrentInfo> {
+        self.cached(|snapshot| snapshot.torrents().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Look something up in the snapshot, returns `None` if no snapshot was taken yet.
+    fn cached<T>(&self, f: impl FnOnce(&ClientSnapshot) -> T) -> Option<T> {
+        self.snapshot.read().unwrap().as_ref().map(f)
+    }
+
+    /// Update the snapshot, does nothing if no snapshot was taken yet.
+    fn update_cached(&self, f: impl FnOnce(&mut ClientSnapshot)) {
+        if let Some(snapshot) = self.snapshot.write().unwrap().as_mut() {
+            f(snapshot);
+        }
+    }
+
+    /// Gets a torrent's info by its info hash from the snapshot, or from the client if there is no snapshot.
+    pub async fn get_torrent_info(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+        if let Some(info) = self.cached(|snapshot| snapshot.get(hash).cloned()) {
+            return Ok(info);
+        }
+
+        self.client.get_torrent(hash).await
     }
 
-    /// Gets a torrent's info from the client.
-    pub async fn get_torrent_info(&self, torrent: &Torrent) -> abstracttorrent::client::ClientResult<Option<TorrentInfo>> {
-        let params = GetTorrentListParams::builder()
-            .hash(&torrent.info_hash())
-            .build();
+    /// Gets a torrent's current info from the client, ignoring the snapshot. Used to follow a torrent
+    /// whose state is changing, like during a recheck.
+    pub async fn fetch_torrent_info(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+        let info = self.client.get_torrent(hash).await?;
+        if let Some(info) = &info {
+            self.update_cached(|snapshot| snapshot.insert(info.clone()));
+        }
+
+        Ok(info)
+    }
 
-        let results = self.client.get_torrent_list(Some(params)).await?;
-        Ok(results.first().cloned())
+    /// Gets the progress of a torrent's download from the client, from `0.0` to `1.0`.
+    pub async fn get_torrent_progress(&self, hash: &str) -> ClientResult<Option<f64>> {
+        self.client.get_torrent_progress(hash).await
     }
 
     /// Checks if the client has the torrent with the exact hash, no like torrents.
-    pub async fn has_exact_torrent(&self, torrent: &Torrent) -> abstracttorrent::client::ClientResult<bool> {
-        let params = GetTorrentListParams::builder()
-            .hash(&torrent.info_hash())
-            .build();
+    pub async fn has_exact_torrent(&self, hash: &str) -> ClientResult<bool> {
+        Ok(self.get_torrent_info(hash).await?.is_some())
+    }
+
+    /// Gets the announce urls of a torrent. They're cached in the snapshot after the first request.
+    pub async fn get_torrent_trackers(&self, torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+        if let Some(trackers) = self.cached(|snapshot| snapshot.trackers(&torrent.hash).cloned()).flatten() {
+            return Ok(trackers);
+        }
 
-        let results = self.client.get_torrent_list(Some(params)).await?;
+        let trackers = self.client.get_torrent_trackers(torrent).await?;
+        self.update_cached(|snapshot| snapshot.set_trackers(&torrent.hash, trackers.clone()));
 
-        Ok(results.iter().any(|info| info.hash == torrent.info_hash()))
+        Ok(trackers)
     }
-}
 
-impl Deref for TorrentClient {
-    type Target = Box<dyn abstracttorrent::client::TorrentClient + Send + Sync>;
-    fn deref(&self) -> &Self::Target { &self.client }
-}
+    /// Gets the files of a torrent with their sizes.
+    pub async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+        self.client.get_torrent_files(torrent).await
+    }
 
-impl DerefMut for TorrentClient {
-    fn deref_mut(&mut self) -> &mut Box<dyn abstracttorrent::client::TorrentClient + Send + Sync> { &mut self.client }
-}
\ No newline at end of file
+    /// Exports the `.torrent` file of a torrent, `None` if the client can't export torrents.
+    pub async fn export_torrent(&self, torrent: &TorrentInfo) -> ClientResult<Option<Vec<u8>>> {
+        self.client.export_torrent(torrent).await
+    }
+
+    pub async fn add_torrent_trackers(&self, torrent: &TorrentInfo, trackers: Vec<String>) -> ClientResult<()> {
+        self.client.add_torrent_trackers(torrent, trackers).await?;
+        self.update_cached(|snapshot| snapshot.clear_trackers(&torrent.hash));
+
+        Ok(())
+    }
+
+    pub async fn add_torrent_tags(&self, torrent: &TorrentInfo, tags: Vec<String>) -> ClientResult<()> {
+        self.client.add_torrent_tags(torrent, tags).await
+    }
+
+    pub async fn recheck_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.client.recheck_torrent(torrent).await
+    }
+
+    pub async fn resume_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.client.resume_torrent(torrent).await
+    }
+
+    pub async fn add_torrent(&self, upload: &TorrentUpload) -> ClientResult<()> {
+        self.client.add_torrent(upload).await?;
+
+        // Keep the snapshot up to date so the torrent isn't added again before the next refresh.
+        if let Ok(torrent) = upload.read_torrent() {
+            let info = TorrentInfo {
+                name: torrent.name.clone(),
+                category: upload.category.clone().unwrap_or_default(),
+                tags: upload.tags.clone(),
+                save_path: upload.save_path.clone(),
+                added_on: Some(crate::util::unix_timestamp()),
+                ..TorrentInfo::from_hash(torrent.info_hash())
+            };
+            self.update_cached(|snapshot| snapshot.insert(info));
+        }
+
+        Ok(())
+    }
+
+    pub async fn remove_torrent(&self, torrent: &TorrentInfo, delete_files: bool) -> ClientResult<()> {
+        self.client.remove_torrent(torrent, delete_files).await?;
+        self.update_cached(|snapshot| snapshot.remove(&torrent.hash));
+
+        Ok(())
+    }
+}
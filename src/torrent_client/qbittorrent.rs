@@ -0,0 +1,411 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use reqwest::multipart::{Form, Part};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::client::qbittorrent::QBittorrentConfig;
+
+use super::{ClientError, ClientResult, ContentLayout, TorrentBackend, TorrentFile, TorrentInfo, TorrentState, TorrentUpload};
+
+/// How many times to look for a torrent that was just added before giving up on it.
+const ADDED_TORRENT_POLLS: u32 = 10;
+
+/// A qbittorrent client, using its web api.
+pub struct QBittorrentBackend {
+    /// The session cookie of the web api is kept by the cookie store of the client.
+    http: reqwest::Client,
+    config: QBittorrentConfig,
+}
+
+/// A torrent returned by `torrents/info`.
+#[derive(Debug, Deserialize)]
+struct QBittorrentTorrent {
+    hash: String,
+    name: String,
+    #[serde(default)]
+    category: String,
+    /// Tags separated by commas, ex: `a, b`.
+    #[serde(default)]
+    tags: String,
+    state: String,
+    progress: f64,
+    save_path: String,
+    #[serde(default)]
+    added_on: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct QBittorrentTracker {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QBittorrentFile {
+    name: String,
+    size: u64,
+}
+
+/// Map the state of a torrent in the web api to a `TorrentState`.
+fn torrent_state(state: &str) -> TorrentState {
+    match state {
+        "uploading" | "stalledUP" | "forcedUP" => TorrentState::Uploading,
+        "downloading" | "stalledDL" | "forcedDL" | "metaDL" | "forcedMetaDL" => TorrentState::Downloading,
+        // Version 5.0 renamed paused to stopped.
+        "pausedUP" | "stoppedUP" => TorrentState::PausedUploading,
+        "pausedDL" | "stoppedDL" => TorrentState::PausedDownloading,
+        "queuedUP" => TorrentState::QueuedUploading,
+        "queuedDL" => TorrentState::QueuedDownloading,
+        "checkingUP" => TorrentState::CheckingUploading,
+        // A torrent waiting for its check, or having its resume data checked, isn't known to be complete yet.
+        "checkingDL" | "checkingResumeData" | "queuedForChecking" => TorrentState::CheckingDownloading,
+        "allocating" => TorrentState::Allocating,
+        "moving" => TorrentState::Moving,
+        "error" | "missingFiles" => TorrentState::Error,
+        _ => TorrentState::Unknown,
+    }
+}
+
+impl From<QBittorrentTorrent> for TorrentInfo {
+    fn from(torrent: QBittorrentTorrent) -> Self {
+        Self {
+            state: torrent_state(&torrent.state),
+            hash: torrent.hash.to_lowercase(),
+            name: torrent.name,
+            category: torrent.category,
+            tags: torrent.tags.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+            save_path: Some(torrent.save_path),
+            added_on: Some(torrent.added_on).filter(|added| *added > 0),
+        }
+    }
+}
+
+/// The value of `contentLayout` in `torrents/add`. A renamed folder is added with its original name and
+/// renamed after adding it.
+fn content_layout_param(layout: &ContentLayout) -> &'static str {
+    match layout {
+        ContentLayout::Original | ContentLayout::Renamed(_) => "Original",
+        ContentLayout::NoSubfolder => "NoSubfolder",
+    }
+}
+
+impl QBittorrentBackend {
+    pub fn new(config: QBittorrentConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            config,
+        }
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!("{}/api/v2/{}", self.config.url.trim_end_matches('/'), path)
+    }
+
+    /// Log in to the web api, the session cookie is kept by the cookie store.
+    async fn authenticate(&self) -> ClientResult<()> {
+        let res = self.http.post(self.api_url("auth/login"))
+            .form(&[("username", &self.config.username), ("password", &self.config.password)])
+            .send().await?
+            .error_for_status()?
+            .text().await?;
+
+        match res.trim() {
+            "Ok." => Ok(()),
+            _ => Err(ClientError::Unauthorized),
+        }
+    }
+
+    /// Send a request built by `request` to the web api.
+    ///
+    /// When qbittorrent responds with `403`, the session expired, so this logs in again and retries the request once.
+    async fn send(&self, request: impl Fn() -> ClientResult<reqwest::RequestBuilder>) -> ClientResult<reqwest::Response> {
+        let res = request()?.send().await?;
+        if res.status() != StatusCode::FORBIDDEN {
+            return Ok(res);
+        }
+
+        debug!("qbittorrent session expired, logging in again...");
+        self.authenticate().await?;
+
+        Ok(request()?.send().await?)
+    }
+
+    /// Post a form to the web api.
+    async fn post_form<T: Serialize + ?Sized + Sync>(&self, path: &str, form: &T) -> ClientResult<reqwest::Response> {
+        self.send(|| Ok(self.http.post(self.api_url(path)).form(form))).await
+    }
+
+    /// Get a path of the web api with a query.
+    async fn get_query<T: Serialize + ?Sized + Sync>(&self, path: &str, query: &T) -> ClientResult<reqwest::Response> {
+        self.send(|| Ok(self.http.get(self.api_url(path)).query(query))).await
+    }
+
+    async fn get_qbittorrent_torrents(&self, hash: Option<&str>) -> ClientResult<Vec<QBittorrentTorrent>> {
+        let query: Vec<(&str, &str)> = hash.into_iter().map(|hash| ("hashes", hash)).collect();
+
+        Ok(self.get_query("torrents/info", &query).await?
+            .error_for_status()?
+            .json().await?)
+    }
+
+    /// The multipart form of `torrents/add` for an upload.
+    fn add_form(upload: &TorrentUpload) -> ClientResult<Form> {
+        let file = Part::bytes(upload.data.clone())
+            .file_name(upload.filename.clone())
+            .mime_str("application/x-bittorrent")?;
+
+        let mut form = Form::new()
+            .part("torrents", file)
+            .text("tags", upload.tags.join(","))
+            // Version 5.0 renamed paused to stopped.
+            .text("paused", upload.paused.to_string())
+            .text("stopped", upload.paused.to_string())
+            .text("contentLayout", content_layout_param(&upload.content_layout));
+
+        if let Some(category) = &upload.category {
+            form = form.text("category", category.clone());
+        }
+        if let Some(save_path) = &upload.save_path {
+            form = form.text("savepath", save_path.clone());
+        }
+
+        Ok(form)
+    }
+
+    /// Rename the folder of a torrent that was just added, qbittorrent can't add a torrent with another folder name.
+    async fn rename_root_folder(&self, upload: &TorrentUpload, root: &str) -> ClientResult<()> {
+        let torrent = upload.read_torrent()?;
+        let hash = torrent.info_hash();
+
+        // qbittorrent adds torrents in the background, so it may not have the torrent yet.
+        let mut polls = 0;
+        while self.get_qbittorrent_torrents(Some(&hash)).await?.is_empty() {
+            polls += 1;
+            if polls >= ADDED_TORRENT_POLLS {
+                return Err(ClientError::Rpc(format!("qbittorrent didn't add {}", upload.filename)));
+            }
+
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+
+        self.post_form("torrents/renameFolder", &[("hash", hash.as_str()), ("oldPath", torrent.name.as_str()), ("newPath", root)]).await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TorrentBackend for QBittorrentBackend {
+    async fn login(&mut self) -> ClientResult<()> {
+        self.authenticate().await
+    }
+
+    async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+        let torrents = self.get_qbittorrent_torrents(None).await?;
+        Ok(torrents.into_iter().map(TorrentInfo::from).collect())
+    }
+
+    async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+        let torrents = self.get_qbittorrent_torrents(Some(hash)).await?;
+
+        Ok(torrents.into_iter()
+            .find(|torrent| torrent.hash.eq_ignore_ascii_case(hash))
+            .map(TorrentInfo::from))
+    }
+
+    async fn get_torrent_trackers(&self, torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+        let trackers: Vec<QBittorrentTracker> = self.get_query("torrents/trackers", &[("hash", &torrent.hash)]).await?
+            .error_for_status()?
+            .json().await?;
+
+        Ok(trackers.into_iter().map(|tracker| tracker.url).collect())
+    }
+
+    async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+        let files: Vec<QBittorrentFile> = self.get_query("torrents/files", &[("hash", &torrent.hash)]).await?
+            .error_for_status()?
+            .json().await?;
+
+        Ok(files.into_iter()
+            .map(|file| TorrentFile::from_content_path(&file.name, file.size))
+            .collect())
+    }
+
+    async fn export_torrent(&self, torrent: &TorrentInfo) -> ClientResult<Option<Vec<u8>>> {
+        let res = self.post_form("torrents/export", &[("hash", &torrent.hash)]).await?;
+
+        // Versions before 4.5 don't have the export endpoint.
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        Ok(Some(res.error_for_status()?.bytes().await?.to_vec()))
+    }
+
+    async fn get_torrent_progress(&self, hash: &str) -> ClientResult<Option<f64>> {
+        let torrents = self.get_qbittorrent_torrents(Some(hash)).await?;
+
+        Ok(torrents.into_iter()
+            .find(|torrent| torrent.hash.eq_ignore_ascii_case(hash))
+            .map(|torrent| torrent.progress))
+    }
+
+    async fn add_torrent_trackers(&self, torrent: &TorrentInfo, trackers: Vec<String>) -> ClientResult<()> {
+        self.post_form("torrents/addTrackers", &[("hash", &torrent.hash), ("urls", &trackers.join("\n"))]).await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    fn supports_tags(&self) -> bool {
+        true
+    }
+
+    async fn add_torrent_tags(&self, torrent: &TorrentInfo, tags: Vec<String>) -> ClientResult<()> {
+        self.post_form("torrents/addTags", &[("hashes", &torrent.hash), ("tags", &tags.join(","))]).await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn recheck_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.post_form("torrents/recheck", &[("hashes", &torrent.hash)]).await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    async fn resume_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        let res = self.post_form("torrents/resume", &[("hashes", &torrent.hash)]).await?;
+
+        // Version 5.0 renamed resume to start.
+        if res.status() == StatusCode::NOT_FOUND {
+            self.post_form("torrents/start", &[("hashes", &torrent.hash)]).await?
+                .error_for_status()?;
+        } else {
+            res.error_for_status()?;
+        }
+
+        Ok(())
+    }
+
+    async fn add_torrent(&self, upload: &TorrentUpload) -> ClientResult<()> {
+        // The form is built again if the request is retried, a multipart body can't be cloned.
+        let res = self.send(|| Ok(self.http.post(self.api_url("torrents/add")).multipart(Self::add_form(upload)?))).await?
+            .error_for_status()?
+            .text().await?;
+
+        if res.trim() != "Ok." {
+            return Err(ClientError::Rpc(format!("qbittorrent didn't add {}: {}", upload.filename, res.trim())));
+        }
+
+        match &upload.content_layout {
+            ContentLayout::Renamed(root) => self.rename_root_folder(upload, root).await,
+            _ => Ok(()),
+        }
+    }
+
+    async fn remove_torrent(&self, torrent: &TorrentInfo, delete_files: bool) -> ClientResult<()> {
+        self.post_form("torrents/delete", &[("hashes", torrent.hash.as_str()), ("deleteFiles", if delete_files { "true" } else { "false" })]).await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{routing::{get, post}, Router};
+
+    use super::*;
+
+    #[test]
+    fn maps_web_api_states() {
+        assert!(matches!(torrent_state("stalledUP"), TorrentState::Uploading));
+        assert!(matches!(torrent_state("forcedUP"), TorrentState::Uploading));
+        assert!(matches!(torrent_state("metaDL"), TorrentState::Downloading));
+        assert!(matches!(torrent_state("stoppedUP"), TorrentState::PausedUploading));
+        assert!(matches!(torrent_state("pausedDL"), TorrentState::PausedDownloading));
+        assert!(matches!(torrent_state("queuedUP"), TorrentState::QueuedUploading));
+        assert!(matches!(torrent_state("checkingResumeData"), TorrentState::CheckingDownloading));
+        assert!(matches!(torrent_state("queuedForChecking"), TorrentState::CheckingDownloading));
+        assert!(matches!(torrent_state("missingFiles"), TorrentState::Error));
+        assert!(matches!(torrent_state("somethingNew"), TorrentState::Unknown));
+    }
+
+    #[test]
+    fn splits_tags() {
+        let info = TorrentInfo::from(QBittorrentTorrent {
+            hash: String::from("ABCDEF"),
+            name: String::from("Name"),
+            category: String::from("movies"),
+            tags: String::from("a, b,,c "),
+            state: String::from("uploading"),
+            progress: 1.0,
+            save_path: String::from("/downloads"),
+            added_on: 0,
+        });
+
+        assert_eq!(info.hash, "abcdef");
+        assert_eq!(info.tags, vec!["a", "b", "c"]);
+        assert_eq!(info.save_path.as_deref(), Some("/downloads"));
+    }
+
+    #[tokio::test]
+    async fn logs_in_again_when_session_expired() {
+        // The session is only valid after a login, like a session that expired since the last run.
+        let logins = Arc::new(AtomicUsize::new(0));
+        let login_count = Arc::clone(&logins);
+        let app = Router::new()
+            .route("/api/v2/auth/login", post(move || {
+                login_count.fetch_add(1, Ordering::SeqCst);
+                async { "Ok." }
+            }))
+            .route("/api/v2/torrents/info", get({
+                let logins = Arc::clone(&logins);
+                move || async move {
+                    match logins.load(Ordering::SeqCst) {
+                        0 => (StatusCode::FORBIDDEN, "Forbidden"),
+                        _ => (StatusCode::OK, "[]"),
+                    }
+                }
+            }));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        let config = QBittorrentConfig { url, username: String::from("admin"), password: String::from("password") };
+        let backend = QBittorrentBackend::new(config, reqwest::Client::new());
+
+        assert!(backend.get_torrents().await.unwrap().is_empty());
+        assert_eq!(logins.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn missing_export_endpoint_exports_nothing() {
+        // Versions before 4.5 answer 404 for the export endpoint.
+        let app = Router::new()
+            .route("/api/v2/auth/login", post(|| async { "Ok." }))
+            .route("/api/v2/torrents/export", post(|| async { (StatusCode::NOT_FOUND, "Not Found") }));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(axum::Server::from_tcp(listener).unwrap().serve(app.into_make_service()));
+
+        let config = QBittorrentConfig { url, username: String::from("admin"), password: String::from("password") };
+        let backend = QBittorrentBackend::new(config, reqwest::Client::new());
+
+        let info = TorrentInfo::from_hash(String::from("abcdef"));
+        assert!(backend.export_torrent(&info).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use reqwest::header::CONTENT_TYPE;
+use tracing::debug;
+
+use crate::config::client::rtorrent::RTorrentConfig;
+
+use super::{ClientError, ClientResult, ContentLayout, TorrentBackend, TorrentFile, TorrentInfo, TorrentState, TorrentUpload};
+use super::xmlrpc::{self, XmlRpcValue};
+
+/// The torrent fields requested from rTorrent, in the order they're returned.
+const TORRENT_FIELDS: [&str; 9] = ["d.hash", "d.name", "d.custom1", "d.directory", "d.is_multi_file", "d.state", "d.is_active", "d.complete", "d.hashing"];
+
+/// An rTorrent client, using its xml-rpc over http or an scgi socket. `custom1` is used for
+/// categories, like ruTorrent does for its labels.
+pub struct RTorrentBackend {
+    http: reqwest::Client,
+    config: RTorrentConfig,
+}
+
+#[derive(Debug)]
+struct RTorrentTorrent {
+    hash: String,
+    name: String,
+    label: String,
+    /// The base path of the torrent's content for multi file torrents, or the directory the file
+    /// is in for single file torrents.
+    directory: String,
+    multi_file: bool,
+    started: bool,
+    active: bool,
+    complete: bool,
+    hashing: bool,
+}
+
+impl RTorrentTorrent {
+    /// Read a torrent from the values of `TORRENT_FIELDS`.
+    fn from_fields(fields: &[XmlRpcValue]) -> ClientResult<Self> {
+        let missing = |i: usize| ClientError::XmlRpc(format!("rtorrent didn't return {} of a torrent", TORRENT_FIELDS[i]));
+        let text = |i: usize| fields.get(i)
+            .and_then(XmlRpcValue::as_str)
+            .map(|s| s.to_string())
+            .ok_or_else(|| missing(i));
+        let flag = |i: usize| fields.get(i)
+            .and_then(XmlRpcValue::as_i64)
+            .map(|v| v != 0)
+            .ok_or_else(|| missing(i));
+
+        Ok(Self {
+            hash: text(0)?.to_lowercase(),
+            name: text(1)?,
+            label: text(2)?,
+            directory: text(3)?,
+            multi_file: flag(4)?,
+            started: flag(5)?,
+            active: flag(6)?,
+            complete: flag(7)?,
+            hashing: flag(8)?,
+        })
+    }
+
+    /// Map the state of the torrent to a `TorrentState`.
+    fn state(&self) -> TorrentState {
+        match (self.hashing, self.started && self.active, self.complete) {
+            (true, _, true) => TorrentState::CheckingUploading,
+            (true, _, false) => TorrentState::CheckingDownloading,
+            // rTorrent calls a torrent that is started but not active paused.
+            (false, false, true) => TorrentState::PausedUploading,
+            (false, false, false) => TorrentState::PausedDownloading,
+            (false, true, true) => TorrentState::Uploading,
+            (false, true, false) => TorrentState::Downloading,
+        }
+    }
+
+    /// The directory the torrent's content is saved in, the parent of its base path.
+    fn save_path(&self) -> String {
+        if self.multi_file {
+            Path::new(&self.directory).parent()
+                .map(|parent| parent.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.directory.clone())
+        } else {
+            self.directory.clone()
+        }
+    }
+}
+
+impl From<RTorrentTorrent> for TorrentInfo {
+    fn from(torrent: RTorrentTorrent) -> Self {
+        Self {
+            state: torrent.state(),
+            save_path: Some(torrent.save_path()),
+            hash: torrent.hash,
+            name: torrent.name,
+            category: torrent.label,
+            tags: vec![],
+            // rTorrent doesn't keep when a torrent was added, only ruTorrent does in a custom field.
+            added_on: None,
+        }
+    }
+}
+
+/// rTorrent expects info hashes in uppercase.
+fn target(hash: &str) -> XmlRpcValue {
+    XmlRpcValue::String(hash.to_uppercase())
+}
+
+/// Quote an argument of a command that is run when a torrent is loaded.
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl RTorrentBackend {
+    pub fn new(config: RTorrentConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            config,
+        }
+    }
+
+    /// Call an xml-rpc method and return its value.
+    async fn call(&self, method: &str, params: Vec<XmlRpcValue>) -> ClientResult<XmlRpcValue> {
+        let body = xmlrpc::encode_call(method, &params);
+
+        let res = if let Some(socket) = &self.config.socket {
+            scgi_request(socket, body).await?
+        } else if let Some(url) = &self.config.url {
+            self.http_request(url, body).await?
+        } else {
+            return Err(ClientError::Rpc(String::from("rtorrent needs either a url or a socket")));
+        };
+
+        xmlrpc::decode_response(&res)
+    }
+
+    async fn http_request(&self, url: &str, body: String) -> ClientResult<String> {
+        let mut req = self.http.post(url)
+            .header(CONTENT_TYPE, "text/xml")
+            .body(body);
+
+        if let Some(username) = &self.config.username {
+            req = req.basic_auth(username, self.config.password.as_ref());
+        }
+
+        let res = req.send().await?;
+        if res.status() == StatusCode::UNAUTHORIZED {
+            return Err(ClientError::Unauthorized);
+        }
+
+        Ok(res.error_for_status()?.text().await?)
+    }
+
+    /// Get a torrent's fields with a `system.multicall`, since rTorrent can't filter `d.multicall2` by hash.
+    async fn get_rtorrent_torrent(&self, hash: &str) -> ClientResult<Option<RTorrentTorrent>> {
+        let calls = TORRENT_FIELDS.iter()
+            .map(|field| {
+                let mut call = BTreeMap::new();
+                call.insert(String::from("methodName"), XmlRpcValue::from(*field));
+                call.insert(String::from("params"), XmlRpcValue::Array(vec![target(hash)]));
+
+                XmlRpcValue::Struct(call)
+            })
+            .collect();
+
+        let res = self.call("system.multicall", vec![XmlRpcValue::Array(calls)]).await?;
+        let results = res.as_array().unwrap_or_default();
+
+        // Each result is either its value in an array, or a fault if the torrent doesn't exist.
+        let mut fields = vec![];
+        for result in results {
+            match result.as_array().and_then(|values| values.first()) {
+                Some(value) => fields.push(value.clone()),
+                None => {
+                    debug!("rtorrent doesn't have {}: {:?}", hash, xmlrpc::fault_error(result));
+                    return Ok(None);
+                },
+            }
+        }
+
+        RTorrentTorrent::from_fields(&fields).map(Some)
+    }
+
+    /// Get the announce urls of a torrent with the tracker group they're in.
+    async fn get_tracker_groups(&self, hash: &str) -> ClientResult<Vec<(i64, String)>> {
+        let res = self.call("t.multicall", vec![target(hash), "".into(), "t.group=".into(), "t.url=".into()]).await?;
+
+        Ok(res.as_array()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|tracker| {
+                let fields = tracker.as_array()?;
+                Some((fields.first()?.as_i64()?, fields.get(1)?.as_str()?.to_string()))
+            })
+            .collect())
+    }
+}
+
+/// Send a request to an scgi socket and return the body of the response.
+#[cfg(unix)]
+async fn scgi_request(socket: &Path, body: String) -> ClientResult<String> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixStream;
+
+    let headers: String = [
+        ("CONTENT_LENGTH", body.len().to_string()),
+        ("SCGI", String::from("1")),
+        ("REQUEST_METHOD", String::from("POST")),
+        ("REQUEST_URI", String::from("/RPC2")),
+    ].iter()
+        .map(|(name, value)| format!("{}\0{}\0", name, value))
+        .collect();
+
+    let mut stream = UnixStream::connect(socket).await?;
+    stream.write_all(format!("{}:{},", headers.len(), headers).as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+
+    let mut res = String::new();
+    stream.read_to_string(&mut res).await?;
+
+    parse_scgi_response(&res)
+}
+
+#[cfg(not(unix))]
+async fn scgi_request(_socket: &Path, _body: String) -> ClientResult<String> {
+    Err(ClientError::Rpc(String::from("scgi sockets are only supported on unix")))
+}
+
+/// Get the body of an scgi response. The response has http style headers, ex: `Status: 200 OK`, and
+/// a response without a status is successful.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn parse_scgi_response(res: &str) -> ClientResult<String> {
+    let (headers, body) = res.split_once("\r\n\r\n")
+        .ok_or_else(|| ClientError::XmlRpc(String::from("scgi response is missing its headers")))?;
+
+    let status = headers.lines()
+        .find_map(|line| line.strip_prefix("Status:"))
+        .map(str::trim);
+    match status {
+        Some(status) if !status.starts_with("200") => Err(ClientError::Rpc(format!("scgi request failed: {}", status))),
+        _ => Ok(body.to_string()),
+    }
+}
+
+#[async_trait]
+impl TorrentBackend for RTorrentBackend {
+    async fn login(&mut self) -> ClientResult<()> {
+        // rTorrent doesn't have sessions, so this only checks that the rpc is reachable.
+        let version = self.call("system.client_version", vec![]).await?;
+        debug!("Connected to rtorrent {}", version.as_str().unwrap_or("unknown"));
+
+        Ok(())
+    }
+
+    async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+        let mut params: Vec<XmlRpcValue> = vec!["".into(), "main".into()];
+        params.extend(TORRENT_FIELDS.iter().map(|field| XmlRpcValue::from(format!("{}=", field))));
+
+        let res = self.call("d.multicall2", params).await?;
+        res.as_array()
+            .unwrap_or_default()
+            .iter()
+            .map(|torrent| {
+                let fields = torrent.as_array().unwrap_or_default();
+                RTorrentTorrent::from_fields(fields).map(TorrentInfo::from)
+            })
+            .collect()
+    }
+
+    async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+        Ok(self.get_rtorrent_torrent(hash).await?.map(TorrentInfo::from))
+    }
+
+    async fn get_torrent_trackers(&self, torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+        let trackers = self.get_tracker_groups(&torrent.hash).await?;
+        Ok(trackers.into_iter().map(|(_, url)| url).collect())
+    }
+
+    async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+        let res = self.call("f.multicall", vec![target(&torrent.hash), "".into(), "f.path=".into(), "f.size_bytes=".into()]).await?;
+
+        // rTorrent's paths are already relative to the content root.
+        Ok(res.as_array()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|file| {
+                let fields = file.as_array()?;
+                Some(TorrentFile {
+                    path: fields.first()?.as_str()?.into(),
+                    length: fields.get(1)?.as_i64()? as u64,
+                })
+            })
+            .collect())
+    }
+
+    async fn get_torrent_progress(&self, hash: &str) -> ClientResult<Option<f64>> {
+        if self.get_rtorrent_torrent(hash).await?.is_none() {
+            return Ok(None);
+        }
+
+        let completed = self.call("d.completed_bytes", vec![target(hash)]).await?.as_i64().unwrap_or_default();
+        let size = self.call("d.size_bytes", vec![target(hash)]).await?.as_i64().unwrap_or_default();
+
+        Ok(Some(match size {
+            0 => 0.0,
+            size => completed as f64 / size as f64,
+        }))
+    }
+
+    async fn add_torrent_trackers(&self, torrent: &TorrentInfo, trackers: Vec<String>) -> ClientResult<()> {
+        let current = self.get_tracker_groups(&torrent.hash).await?;
+
+        // Each new tracker is put in its own group after the existing ones, like a new tier.
+        let mut group = current.iter().map(|(group, _)| group + 1).max().unwrap_or(0);
+        for url in trackers {
+            if current.iter().any(|(_, current)| *current == url) {
+                continue;
+            }
+
+            self.call("d.tracker.insert", vec![target(&torrent.hash), group.to_string().into(), url.into()]).await?;
+            group += 1;
+        }
+
+        Ok(())
+    }
+
+    async fn add_torrent(&self, upload: &TorrentUpload) -> ClientResult<()> {
+        let method = if upload.paused {
+            "load.raw_verbose"
+        } else {
+            "load.raw_start_verbose"
+        };
+
+        let mut params = vec!["".into(), XmlRpcValue::Base64(upload.data.clone())];
+        if let Some(save_path) = &upload.save_path {
+            // `d.directory_base` is the exact folder of the content, so it can be given any name.
+            let directory = match &upload.content_layout {
+                ContentLayout::Original => format!("d.directory.set={}", quote(save_path)),
+                ContentLayout::Renamed(root) => {
+                    let base = Path::new(save_path).join(root);
+                    format!("d.directory_base.set={}", quote(&base.to_string_lossy()))
+                },
+                ContentLayout::NoSubfolder => format!("d.directory_base.set={}", quote(save_path)),
+            };
+            params.push(directory.into());
+        }
+        if let Some(category) = &upload.category {
+            params.push(format!("d.custom1.set={}", quote(category)).into());
+        }
+
+        if !upload.tags.is_empty() {
+            debug!("rTorrent doesn't support tags, not adding {:?} to {}", upload.tags, upload.filename);
+        }
+
+        self.call(method, params).await?;
+        Ok(())
+    }
+
+    async fn recheck_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.call("d.check_hash", vec![target(&torrent.hash)]).await?;
+        Ok(())
+    }
+
+    async fn resume_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        // A torrent added with `load.raw_verbose` is closed, `d.start` opens it as well.
+        self.call("d.start", vec![target(&torrent.hash)]).await?;
+        Ok(())
+    }
+
+    async fn remove_torrent(&self, torrent: &TorrentInfo, delete_files: bool) -> ClientResult<()> {
+        // rTorrent never deletes files itself, this marks the torrent for the erasedata plugin of ruTorrent.
+        if delete_files {
+            self.call("d.custom5.set", vec![target(&torrent.hash), "1".into()]).await?;
+        }
+
+        self.call("d.erase", vec![target(&torrent.hash)]).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(hashing: bool, started: bool, active: bool, complete: bool) -> RTorrentTorrent {
+        RTorrentTorrent {
+            hash: String::from("abcdef"),
+            name: String::from("Name"),
+            label: String::from("movies"),
+            directory: String::from("/downloads/Name"),
+            multi_file: true,
+            started,
+            active,
+            complete,
+            hashing,
+        }
+    }
+
+    #[test]
+    fn maps_states() {
+        assert!(matches!(torrent(true, true, true, true).state(), TorrentState::CheckingUploading));
+        assert!(matches!(torrent(true, false, false, false).state(), TorrentState::CheckingDownloading));
+        assert!(matches!(torrent(false, true, false, true).state(), TorrentState::PausedUploading));
+        assert!(matches!(torrent(false, false, false, false).state(), TorrentState::PausedDownloading));
+        assert!(matches!(torrent(false, true, true, true).state(), TorrentState::Uploading));
+        assert!(matches!(torrent(false, true, true, false).state(), TorrentState::Downloading));
+    }
+
+    #[test]
+    fn save_path_is_the_parent_of_multi_file_torrents() {
+        assert_eq!(torrent(false, true, true, true).save_path(), "/downloads");
+
+        let single = RTorrentTorrent {
+            directory: String::from("/downloads"),
+            multi_file: false,
+            ..torrent(false, true, true, true)
+        };
+        assert_eq!(single.save_path(), "/downloads");
+    }
+
+    #[test]
+    fn reads_torrent_fields() {
+        let flag = XmlRpcValue::Int;
+        let fields: Vec<XmlRpcValue> = vec!["ABCDEF".into(), "Name".into(), "movies".into(), "/downloads/Name".into(),
+            flag(1), flag(1), flag(0), flag(1), flag(0)];
+        let torrent = RTorrentTorrent::from_fields(&fields).unwrap();
+
+        assert_eq!(torrent.hash, "abcdef");
+        assert!(matches!(torrent.state(), TorrentState::PausedUploading));
+        assert!(matches!(RTorrentTorrent::from_fields(&fields[..4]), Err(ClientError::XmlRpc(_))));
+    }
+
+    #[test]
+    fn parses_scgi_responses() {
+        let body = parse_scgi_response("Status: 200 OK\r\nContent-Type: text/xml\r\n\r\n<methodResponse/>").unwrap();
+        assert_eq!(body, "<methodResponse/>");
+
+        // A response without a status is successful.
+        let body = parse_scgi_response("Content-Type: text/xml\r\n\r\nbody").unwrap();
+        assert_eq!(body, "body");
+
+        let err = parse_scgi_response("Status: 500 Internal Server Error\r\n\r\n").unwrap_err();
+        assert!(matches!(err, ClientError::Rpc(message) if message.contains("500")));
+
+        assert!(matches!(parse_scgi_response("<methodResponse/>"), Err(ClientError::XmlRpc(_))));
+    }
+
+    /// Calls are sent over an scgi socket to a stand-in for rTorrent.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn calls_over_scgi() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::UnixListener;
+
+        let socket = std::env::temp_dir().join(format!("crate-rtorrent-{}.sock", std::process::id()));
+        std::fs::remove_file(&socket).ok();
+        let listener = UnixListener::bind(&socket).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            // Read the netstring of the headers, then the body with the length from them.
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            let body = loop {
+                let read = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((len, rest)) = text.split_once(':') {
+                    let len: usize = len.parse().unwrap();
+                    if rest.len() > len {
+                        let headers = &rest[..len];
+                        let content_length: usize = headers.split('\0')
+                            .skip_while(|name| *name != "CONTENT_LENGTH")
+                            .nth(1).unwrap()
+                            .parse().unwrap();
+                        let body = &rest[len + 1..];
+                        if body.len() >= content_length {
+                            break body.to_string();
+                        }
+                    }
+                }
+            };
+
+            let response = "<methodResponse><params><param><value><i8>4294967296</i8></value></param></params></methodResponse>";
+            stream.write_all(format!("Status: 200 OK\r\nContent-Type: text/xml\r\n\r\n{}", response).as_bytes()).await.unwrap();
+
+            body
+        });
+
+        let backend = RTorrentBackend::new(RTorrentConfig {
+            url: None,
+            socket: Some(socket.clone()),
+            username: None,
+            password: None,
+        }, reqwest::Client::new());
+        let res = backend.call("d.size_bytes", vec![target("abcdef")]).await.unwrap();
+        let body = server.await.unwrap();
+        std::fs::remove_file(&socket).ok();
+
+        assert_eq!(res, XmlRpcValue::Int(4294967296));
+        assert!(body.contains("<methodName>d.size_bytes</methodName>"));
+        assert!(body.contains("<string>ABCDEF</string>"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use super::TorrentInfo;
+
+/// The torrents of a client at one point in time, indexed by info hash. The trackers of a torrent
+/// are only fetched when they're first needed, and are then cached in the snapshot.
+#[derive(Debug, Default)]
+pub struct ClientSnapshot {
+    torrents: HashMap<String, TorrentInfo>,
+    trackers: HashMap<String, Vec<String>>,
+}
+
+impl ClientSnapshot {
+    pub fn new(torrents: Vec<TorrentInfo>) -> Self {
+        Self {
+            torrents: torrents.into_iter()
+                .map(|info| (info.hash.to_lowercase(), info))
+                .collect(),
+            trackers: HashMap::new(),
+        }
+    }
+
+    pub fn torrents(&self) -> impl Iterator<Item = &TorrentInfo> {
+        self.torrents.values()
+    }
+
+    pub fn get(&self, hash: &str) -> Option<&TorrentInfo> {
+        self.torrents.get(&hash.to_lowercase())
+    }
+
+    /// Add a torrent that was added to the client after the snapshot was taken.
+    pub fn insert(&mut self, info: TorrentInfo) {
+        self.torrents.insert(info.hash.to_lowercase(), info);
+    }
+
+    pub fn remove(&mut self, hash: &str) {
+        let hash = hash.to_lowercase();
+        self.torrents.remove(&hash);
+        self.trackers.remove(&hash);
+    }
+
+    /// The cached trackers of a torrent, if they were fetched already.
+    pub fn trackers(&self, hash: &str) -> Option<&Vec<String>> {
+        self.trackers.get(&hash.to_lowercase())
+    }
+
+    pub fn set_trackers(&mut self, hash: &str, trackers: Vec<String>) {
+        self.trackers.insert(hash.to_lowercase(), trackers);
+    }
+
+    /// Forget the cached trackers of a torrent, ex: after trackers were added to it.
+    pub fn clear_trackers(&mut self, hash: &str) {
+        self.trackers.remove(&hash.to_lowercase());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use crate::torrent_client::*;
+
+    /// A client that counts the requests it gets for single torrents and their trackers.
+    #[derive(Default)]
+    struct CountingBackend {
+        torrents: Mutex<Vec<TorrentInfo>>,
+        torrent_requests: AtomicUsize,
+        tracker_requests: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TorrentBackend for Arc<CountingBackend> {
+        async fn login(&mut self) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+            Ok(self.torrents.lock().unwrap().clone())
+        }
+
+        async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+            self.torrent_requests.fetch_add(1, Ordering::SeqCst);
+            Ok(self.torrents.lock().unwrap().iter().find(|info| info.hash == hash.to_lowercase()).cloned())
+        }
+
+        async fn get_torrent_trackers(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+            self.tracker_requests.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![String::from("https://tracker/announce")])
+        }
+
+        async fn get_torrent_files(&self, _torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+            Ok(vec![])
+        }
+
+        async fn get_torrent_progress(&self, _hash: &str) -> ClientResult<Option<f64>> {
+            Ok(None)
+        }
+
+        async fn add_torrent_trackers(&self, _torrent: &TorrentInfo, _trackers: Vec<String>) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn recheck_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn resume_torrent(&self, _torrent: &TorrentInfo) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn add_torrent(&self, _upload: &TorrentUpload) -> ClientResult<()> {
+            Ok(())
+        }
+
+        async fn remove_torrent(&self, _torrent: &TorrentInfo, _delete_files: bool) -> ClientResult<()> {
+            Ok(())
+        }
+    }
+
+    fn torrent(hash: &str) -> TorrentInfo {
+        TorrentInfo { state: TorrentState::Uploading, ..TorrentInfo::from_hash(hash.to_string()) }
+    }
+
+    #[tokio::test]
+    async fn lookups_are_answered_from_the_last_refresh() {
+        let backend = Arc::new(CountingBackend::default());
+        *backend.torrents.lock().unwrap() = vec![torrent("aa"), torrent("bb")];
+        let client = TorrentClient::new(String::from("stand-in"), Box::new(backend.clone()));
+
+        // Without a snapshot the client is asked.
+        assert!(client.get_torrent_info("aa").await.unwrap().is_some());
+        assert_eq!(backend.torrent_requests.load(Ordering::SeqCst), 1);
+
+        assert_eq!(client.refresh_snapshot().await.unwrap().len(), 2);
+        assert_eq!(client.get_torrent_info("AA").await.unwrap().unwrap().hash, "aa");
+        assert!(client.get_torrent_info("cc").await.unwrap().is_none());
+        assert_eq!(backend.torrent_requests.load(Ordering::SeqCst), 1);
+
+        // Changes in the client show up after the next refresh.
+        *backend.torrents.lock().unwrap() = vec![torrent("bb"), torrent("cc")];
+        assert!(client.get_torrent_info("aa").await.unwrap().is_some());
+        client.refresh_snapshot().await.unwrap();
+        assert!(client.get_torrent_info("aa").await.unwrap().is_none());
+        assert!(client.has_exact_torrent("cc").await.unwrap());
+        assert_eq!(backend.torrent_requests.load(Ordering::SeqCst), 1);
+
+        let mut hashes: Vec<String> = client.snapshot_torrents().into_iter().map(|info| info.hash).collect();
+        hashes.sort();
+        assert_eq!(hashes, vec!["bb", "cc"]);
+    }
+
+    #[tokio::test]
+    async fn trackers_are_cached_until_they_change() {
+        let backend = Arc::new(CountingBackend::default());
+        *backend.torrents.lock().unwrap() = vec![torrent("aa")];
+        let client = TorrentClient::new(String::from("stand-in"), Box::new(backend.clone()));
+        client.refresh_snapshot().await.unwrap();
+
+        let info = torrent("aa");
+        client.get_torrent_trackers(&info).await.unwrap();
+        client.get_torrent_trackers(&info).await.unwrap();
+        assert_eq!(backend.tracker_requests.load(Ordering::SeqCst), 1);
+
+        client.add_torrent_trackers(&info, vec![String::from("https://other/announce")]).await.unwrap();
+        client.get_torrent_trackers(&info).await.unwrap();
+        assert_eq!(backend.tracker_requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,169 @@
+use std::path::PathBuf;
+
+use lava_torrent::torrent::v1::Torrent;
+
+use super::ClientResult;
+
+/// The state of a torrent in the torrent client. Each backend maps the states of its client to these.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TorrentState {
+    Error,
+    Uploading,
+    PausedUploading,
+    QueuedUploading,
+    CheckingUploading,
+    Downloading,
+    PausedDownloading,
+    QueuedDownloading,
+    CheckingDownloading,
+    Allocating,
+    Moving,
+    Unknown,
+}
+
+/// A torrent in the torrent client.
+#[derive(Debug, Clone)]
+pub struct TorrentInfo {
+    /// Info hash of the torrent, in lowercase hex.
+    pub hash: String,
+    pub name: String,
+    /// Category of the torrent, empty if it doesn't have one.
+    pub category: String,
+    pub tags: Vec<String>,
+    pub state: TorrentState,
+    /// The directory the torrent's content is saved in, if the client reports it.
+    pub save_path: Option<String>,
+    /// When the torrent was added to the client as a unix timestamp, if the client reports it.
+    pub added_on: Option<i64>,
+}
+
+impl TorrentInfo {
+    /// Create a torrent info with only its hash, used to make requests for a torrent.
+    pub fn from_hash(hash: String) -> Self {
+        Self {
+            hash: hash.to_lowercase(),
+            name: String::new(),
+            category: String::new(),
+            tags: vec![],
+            state: TorrentState::Unknown,
+            save_path: None,
+            added_on: None,
+        }
+    }
+}
+
+/// A file of a torrent in the torrent client.
+#[derive(Debug, Clone)]
+pub struct TorrentFile {
+    /// Path of the file relative to the torrent's content root, like in the `.torrent` file. The path
+    /// of the file of a single file torrent is its name.
+    pub path: PathBuf,
+    pub length: u64,
+}
+
+impl TorrentFile {
+    /// Create a file from a path that includes the root directory of a multi file torrent, like most
+    /// clients report them.
+    pub fn from_content_path(path: &str, length: u64) -> Self {
+        let path = PathBuf::from(path);
+
+        // Only multi file torrents have a root directory, a single file torrent's path is only its name.
+        let path = match path.components().count() {
+            0 | 1 => path,
+            _ => path.components().skip(1).collect(),
+        };
+
+        Self {
+            path,
+            length,
+        }
+    }
+}
+
+/// A torrent file to add to the torrent client.
+#[derive(Debug, Clone, Default)]
+pub struct TorrentUpload {
+    /// File name of the torrent file, ex: `<hash>.torrent`.
+    pub filename: String,
+    /// The bencoded torrent file.
+    pub data: Vec<u8>,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// The directory to save the torrent's content in. The client's default is used if this isn't set.
+    pub save_path: Option<String>,
+    /// Add the torrent without starting it.
+    pub paused: bool,
+    /// Where the content of a multi file torrent is put in the save path.
+    pub content_layout: ContentLayout,
+}
+
+impl TorrentUpload {
+    pub fn builder() -> TorrentUploadBuilder {
+        TorrentUploadBuilder::default()
+    }
+
+    /// Read the torrent being added, for clients that need its name or files to lay out its content.
+    pub fn read_torrent(&self) -> ClientResult<Torrent> {
+        Ok(Torrent::read_from_bytes(&self.data)?)
+    }
+}
+
+/// Where the content of a multi file torrent is put in its save path. Single file torrents always use
+/// `Original`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ContentLayout {
+    /// In a folder named after the torrent.
+    #[default]
+    Original,
+    /// In a folder with another name, ex: the folder of a local torrent with the same files.
+    Renamed(String),
+    /// Directly in the save path, without a folder.
+    NoSubfolder,
+}
+
+#[derive(Debug, Default)]
+pub struct TorrentUploadBuilder {
+    upload: TorrentUpload,
+}
+
+impl TorrentUploadBuilder {
+    pub fn torrent_data(mut self, filename: String, data: Vec<u8>) -> TorrentUploadBuilder {
+        self.upload.filename = filename;
+        self.upload.data = data;
+        self
+    }
+
+    pub fn category(mut self, category: String) -> TorrentUploadBuilder {
+        self.upload.category = Some(category);
+        self
+    }
+
+    pub fn tags(mut self, tags: Vec<String>) -> TorrentUploadBuilder {
+        self.upload.tags.extend(tags);
+        self
+    }
+
+    pub fn tag(mut self, tag: String) -> TorrentUploadBuilder {
+        self.upload.tags.push(tag);
+        self
+    }
+
+    pub fn save_path(mut self, save_path: String) -> TorrentUploadBuilder {
+        self.upload.save_path = Some(save_path);
+        self
+    }
+
+    pub fn paused(mut self) -> TorrentUploadBuilder {
+        self.upload.paused = true;
+        self
+    }
+
+    pub fn content_layout(mut self, content_layout: ContentLayout) -> TorrentUploadBuilder {
+        self.upload.content_layout = content_layout;
+        self
+    }
+
+    pub fn build(self) -> TorrentUpload {
+        self.upload
+    }
+}
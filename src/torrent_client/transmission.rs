@@ -0,0 +1,353 @@
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::config::client::transmission::TransmissionConfig;
+
+use super::{ClientError, ClientResult, ContentLayout, TorrentBackend, TorrentFile, TorrentInfo, TorrentState, TorrentUpload};
+
+/// The header transmission uses to protect its rpc from csrf.
+const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+
+/// The torrent fields requested from transmission.
+const TORRENT_FIELDS: [&str; 9] = ["hashString", "name", "status", "percentDone", "labels", "downloadDir", "error", "trackers", "addedDate"];
+
+/// A transmission client, using its json rpc.
+pub struct TransmissionBackend {
+    http: reqwest::Client,
+    config: TransmissionConfig,
+    /// The session id of the last response, it has to be sent with every request.
+    session_id: RwLock<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    result: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransmissionTorrent {
+    hash_string: String,
+    name: String,
+    status: i64,
+    percent_done: f64,
+    #[serde(default)]
+    labels: Vec<String>,
+    download_dir: Option<String>,
+    #[serde(default)]
+    error: i64,
+    #[serde(default)]
+    trackers: Vec<TransmissionTracker>,
+    #[serde(default)]
+    added_date: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransmissionTracker {
+    announce: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransmissionFile {
+    name: String,
+    length: u64,
+}
+
+impl TransmissionTorrent {
+    /// Map the status of the torrent to a `TorrentState`.
+    fn state(&self) -> TorrentState {
+        let done = self.percent_done >= 1.0;
+
+        match (self.error, self.status) {
+            (error, _) if error != 0 => TorrentState::Error,
+            (_, 0) if done => TorrentState::PausedUploading,
+            (_, 0) => TorrentState::PausedDownloading,
+            (_, 1) | (_, 2) if done => TorrentState::CheckingUploading,
+            (_, 1) | (_, 2) => TorrentState::CheckingDownloading,
+            (_, 3) => TorrentState::QueuedDownloading,
+            (_, 4) => TorrentState::Downloading,
+            (_, 5) => TorrentState::QueuedUploading,
+            (_, 6) => TorrentState::Uploading,
+            _ => TorrentState::Unknown,
+        }
+    }
+}
+
+impl From<TransmissionTorrent> for TorrentInfo {
+    fn from(torrent: TransmissionTorrent) -> Self {
+        Self {
+            state: torrent.state(),
+            hash: torrent.hash_string.to_lowercase(),
+            name: torrent.name,
+            // Transmission doesn't have categories, the category of added torrents is stored as their first
+            // label. All labels are kept as tags too, since a torrent that wasn't added by cross-seed may not
+            // have a category label.
+            category: torrent.labels.first().cloned().unwrap_or_default(),
+            tags: torrent.labels,
+            save_path: torrent.download_dir,
+            added_on: Some(torrent.added_date).filter(|added| *added > 0),
+        }
+    }
+}
+
+impl TransmissionBackend {
+    pub fn new(config: TransmissionConfig, http: reqwest::Client) -> Self {
+        Self {
+            http,
+            config,
+            session_id: RwLock::new(None),
+        }
+    }
+
+    /// Call an rpc method and return the arguments of the response.
+    ///
+    /// When transmission responds with `409`, the request is retried with the session id it responded with.
+    async fn call(&self, method: &str, arguments: Value) -> ClientResult<Value> {
+        let body = json!({
+            "method": method,
+            "arguments": arguments,
+        });
+
+        for _ in 0..2 {
+            let mut req = self.http.post(&self.config.url).json(&body);
+
+            if let Some(username) = &self.config.username {
+                req = req.basic_auth(username, self.config.password.as_ref());
+            }
+            if let Some(session_id) = self.session_id.read().unwrap().as_ref() {
+                req = req.header(SESSION_ID_HEADER, session_id);
+            }
+
+            let res = req.send().await?;
+            match res.status() {
+                StatusCode::CONFLICT => {
+                    debug!("Transmission session id expired, retrying with the new one...");
+
+                    let session_id = res.headers().get(SESSION_ID_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .map(|value| value.to_string());
+                    *self.session_id.write().unwrap() = session_id;
+                },
+                StatusCode::UNAUTHORIZED => return Err(ClientError::Unauthorized),
+                _ => {
+                    let res: RpcResponse = res.error_for_status()?.json().await?;
+
+                    if res.result != "success" {
+                        return Err(ClientError::Rpc(res.result));
+                    }
+
+                    return Ok(res.arguments);
+                },
+            }
+        }
+
+        Err(ClientError::Rpc(String::from("transmission kept rejecting the session id")))
+    }
+
+    async fn get_transmission_torrents(&self, ids: Option<Vec<&str>>) -> ClientResult<Vec<TransmissionTorrent>> {
+        let mut arguments = json!({ "fields": TORRENT_FIELDS });
+        if let Some(ids) = ids {
+            arguments["ids"] = json!(ids);
+        }
+
+        let res = self.call("torrent-get", arguments).await?;
+        Ok(serde_json::from_value(res["torrents"].clone())?)
+    }
+}
+
+#[async_trait]
+impl TorrentBackend for TransmissionBackend {
+    async fn login(&mut self) -> ClientResult<()> {
+        // Transmission doesn't have sessions to log in to, so this only checks that the rpc is reachable
+        // and gets the first session id.
+        self.call("session-get", json!({ "fields": ["version"] })).await?;
+        Ok(())
+    }
+
+    async fn get_torrents(&self) -> ClientResult<Vec<TorrentInfo>> {
+        let torrents = self.get_transmission_torrents(None).await?;
+        Ok(torrents.into_iter().map(TorrentInfo::from).collect())
+    }
+
+    async fn get_torrent(&self, hash: &str) -> ClientResult<Option<TorrentInfo>> {
+        let torrents = self.get_transmission_torrents(Some(vec![hash])).await?;
+        Ok(torrents.into_iter().next().map(TorrentInfo::from))
+    }
+
+    async fn get_torrent_trackers(&self, torrent: &TorrentInfo) -> ClientResult<Vec<String>> {
+        let torrents = self.get_transmission_torrents(Some(vec![&torrent.hash])).await?;
+
+        Ok(torrents.into_iter()
+            .flat_map(|torrent| torrent.trackers)
+            .map(|tracker| tracker.announce)
+            .collect())
+    }
+
+    async fn get_torrent_files(&self, torrent: &TorrentInfo) -> ClientResult<Vec<TorrentFile>> {
+        let res = self.call("torrent-get", json!({
+            "ids": [torrent.hash],
+            "fields": ["files"],
+        })).await?;
+
+        let files: Vec<TransmissionFile> = serde_json::from_value(res["torrents"][0]["files"].clone())?;
+        Ok(files.into_iter()
+            .map(|file| TorrentFile::from_content_path(&file.name, file.length))
+            .collect())
+    }
+
+    async fn get_torrent_progress(&self, hash: &str) -> ClientResult<Option<f64>> {
+        let torrents = self.get_transmission_torrents(Some(vec![hash])).await?;
+        Ok(torrents.first().map(|torrent| torrent.percent_done))
+    }
+
+    async fn add_torrent_trackers(&self, torrent: &TorrentInfo, trackers: Vec<String>) -> ClientResult<()> {
+        self.call("torrent-set", json!({
+            "ids": [torrent.hash],
+            "trackerAdd": trackers,
+        })).await?;
+
+        Ok(())
+    }
+
+    fn supports_tags(&self) -> bool {
+        true
+    }
+
+    async fn add_torrent_tags(&self, torrent: &TorrentInfo, tags: Vec<String>) -> ClientResult<()> {
+        // Setting labels replaces them, so the new tags are merged with the current labels.
+        let mut labels: Vec<String> = self.get_transmission_torrents(Some(vec![&torrent.hash])).await?
+            .into_iter()
+            .flat_map(|torrent| torrent.labels)
+            .collect();
+        for tag in tags {
+            if !labels.contains(&tag) {
+                labels.push(tag);
+            }
+        }
+
+        self.call("torrent-set", json!({
+            "ids": [torrent.hash],
+            "labels": labels,
+        })).await?;
+
+        Ok(())
+    }
+
+    async fn recheck_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.call("torrent-verify", json!({ "ids": [torrent.hash] })).await?;
+        Ok(())
+    }
+
+    async fn resume_torrent(&self, torrent: &TorrentInfo) -> ClientResult<()> {
+        self.call("torrent-start", json!({ "ids": [torrent.hash] })).await?;
+        Ok(())
+    }
+
+    async fn add_torrent(&self, upload: &TorrentUpload) -> ClientResult<()> {
+        let mut arguments = json!({
+            "metainfo": base64::encode(&upload.data),
+            "paused": upload.paused,
+        });
+        if let Some(save_path) = &upload.save_path {
+            arguments["download-dir"] = json!(save_path);
+        }
+
+        let res = self.call("torrent-add", arguments).await?;
+        let hash = res["torrent-added"]["hashString"].as_str()
+            .or_else(|| res["torrent-duplicate"]["hashString"].as_str())
+            .map(|hash| hash.to_string());
+
+        // Labels are set after adding since older versions of transmission don't accept them in torrent-add.
+        // The category is the first label, so it's read back as the category.
+        let mut labels: Vec<&String> = vec![];
+        for label in upload.category.iter().chain(upload.tags.iter()) {
+            if !label.is_empty() && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        if let (Some(hash), false) = (&hash, labels.is_empty()) {
+            self.call("torrent-set", json!({
+                "ids": [hash],
+                "labels": labels,
+            })).await?;
+        }
+
+        match (&upload.content_layout, hash) {
+            (ContentLayout::Renamed(root), Some(hash)) => {
+                let torrent = upload.read_torrent()?;
+                self.call("torrent-rename-path", json!({
+                    "ids": [hash],
+                    "path": torrent.name,
+                    "name": root,
+                })).await?;
+            },
+            (ContentLayout::NoSubfolder, _) => {
+                warn!("Transmission can't add {} without its folder, its content may not be found", upload.filename);
+            },
+            _ => {},
+        }
+
+        Ok(())
+    }
+
+    async fn remove_torrent(&self, torrent: &TorrentInfo, delete_files: bool) -> ClientResult<()> {
+        self.call("torrent-remove", json!({
+            "ids": [torrent.hash],
+            "delete-local-data": delete_files,
+        })).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(status: i64, percent_done: f64, error: i64, labels: &[&str]) -> TransmissionTorrent {
+        TransmissionTorrent {
+            hash_string: String::from("ABCDEF"),
+            name: String::from("Name"),
+            status,
+            percent_done,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            download_dir: Some(String::from("/downloads")),
+            error,
+            trackers: vec![],
+            added_date: 0,
+        }
+    }
+
+    #[test]
+    fn maps_statuses() {
+        assert!(matches!(torrent(0, 1.0, 0, &[]).state(), TorrentState::PausedUploading));
+        assert!(matches!(torrent(0, 0.5, 0, &[]).state(), TorrentState::PausedDownloading));
+        assert!(matches!(torrent(1, 1.0, 0, &[]).state(), TorrentState::CheckingUploading));
+        assert!(matches!(torrent(2, 0.5, 0, &[]).state(), TorrentState::CheckingDownloading));
+        assert!(matches!(torrent(3, 0.5, 0, &[]).state(), TorrentState::QueuedDownloading));
+        assert!(matches!(torrent(4, 0.5, 0, &[]).state(), TorrentState::Downloading));
+        assert!(matches!(torrent(5, 1.0, 0, &[]).state(), TorrentState::QueuedUploading));
+        assert!(matches!(torrent(6, 1.0, 0, &[]).state(), TorrentState::Uploading));
+        assert!(matches!(torrent(6, 1.0, 3, &[]).state(), TorrentState::Error));
+        assert!(matches!(torrent(7, 1.0, 0, &[]).state(), TorrentState::Unknown));
+    }
+
+    #[test]
+    fn first_label_is_the_category() {
+        let info = TorrentInfo::from(torrent(6, 1.0, 0, &["movies", "cross-seed"]));
+        assert_eq!(info.hash, "abcdef");
+        assert_eq!(info.category, "movies");
+        assert_eq!(info.tags, vec!["movies", "cross-seed"]);
+
+        let info = TorrentInfo::from(torrent(6, 1.0, 0, &[]));
+        assert_eq!(info.category, "");
+        assert!(info.tags.is_empty());
+    }
+}
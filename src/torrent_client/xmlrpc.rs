@@ -0,0 +1,505 @@
+use std::collections::BTreeMap;
+
+use super::{ClientError, ClientResult};
+
+/// A value in an xml-rpc call or response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlRpcValue {
+    Int(i64),
+    Bool(bool),
+    Double(f64),
+    String(String),
+    Base64(Vec<u8>),
+    Array(Vec<XmlRpcValue>),
+    Struct(BTreeMap<String, XmlRpcValue>),
+    Nil,
+}
+
+impl XmlRpcValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            XmlRpcValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            XmlRpcValue::Int(i) => Some(*i),
+            XmlRpcValue::Bool(b) => Some(*b as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[XmlRpcValue]> {
+        match self {
+            XmlRpcValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Get a member of a struct.
+    pub fn get(&self, name: &str) -> Option<&XmlRpcValue> {
+        match self {
+            XmlRpcValue::Struct(members) => members.get(name),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for XmlRpcValue {
+    fn from(s: &str) -> Self {
+        XmlRpcValue::String(s.to_string())
+    }
+}
+
+impl From<String> for XmlRpcValue {
+    fn from(s: String) -> Self {
+        XmlRpcValue::String(s)
+    }
+}
+
+impl From<i64> for XmlRpcValue {
+    fn from(i: i64) -> Self {
+        XmlRpcValue::Int(i)
+    }
+}
+
+impl From<bool> for XmlRpcValue {
+    fn from(b: bool) -> Self {
+        XmlRpcValue::Bool(b)
+    }
+}
+
+/// Encode a method call to send to an xml-rpc server.
+pub fn encode_call(method: &str, params: &[XmlRpcValue]) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0"?><methodCall><methodName>"#);
+    xml.push_str(&escape(method));
+    xml.push_str("</methodName><params>");
+
+    for param in params {
+        xml.push_str("<param>");
+        encode_value(param, &mut xml);
+        xml.push_str("</param>");
+    }
+
+    xml.push_str("</params></methodCall>");
+    xml
+}
+
+fn encode_value(value: &XmlRpcValue, xml: &mut String) {
+    xml.push_str("<value>");
+
+    match value {
+        // rTorrent, like most servers, understands the `i8` extension for 64 bit ints.
+        XmlRpcValue::Int(i) if i32::try_from(*i).is_ok() => {
+            xml.push_str(&format!("<i4>{}</i4>", i));
+        },
+        XmlRpcValue::Int(i) => xml.push_str(&format!("<i8>{}</i8>", i)),
+        XmlRpcValue::Bool(b) => xml.push_str(&format!("<boolean>{}</boolean>", *b as u8)),
+        XmlRpcValue::Double(d) => xml.push_str(&format!("<double>{}</double>", d)),
+        XmlRpcValue::String(s) => xml.push_str(&format!("<string>{}</string>", escape(s))),
+        XmlRpcValue::Base64(data) => xml.push_str(&format!("<base64>{}</base64>", base64::encode(data))),
+        XmlRpcValue::Array(values) => {
+            xml.push_str("<array><data>");
+            for value in values {
+                encode_value(value, xml);
+            }
+            xml.push_str("</data></array>");
+        },
+        XmlRpcValue::Struct(members) => {
+            xml.push_str("<struct>");
+            for (name, value) in members {
+                xml.push_str(&format!("<member><name>{}</name>", escape(name)));
+                encode_value(value, xml);
+                xml.push_str("</member>");
+            }
+            xml.push_str("</struct>");
+        },
+        XmlRpcValue::Nil => xml.push_str("<nil/>"),
+    }
+
+    xml.push_str("</value>");
+}
+
+/// Decode the response to a method call, returning its value. A fault response is returned as `ClientError::Rpc`.
+pub fn decode_response(xml: &str) -> ClientResult<XmlRpcValue> {
+    let mut parser = Parser::new(xml);
+    parser.open("methodResponse")?;
+
+    let tag = parser.tag()?;
+    match (tag.name, tag.closing) {
+        ("params", false) => {
+            if tag.empty || parser.peek_tag()?.closing {
+                return Ok(XmlRpcValue::Nil);
+            }
+
+            parser.open("param")?;
+            parser.value()
+        },
+        ("fault", false) => {
+            let fault = parser.value()?;
+            Err(fault_error(&fault))
+        },
+        (name, _) => Err(invalid(format!("unexpected <{}> in the response", name))),
+    }
+}
+
+/// Convert a fault struct to an error. `system.multicall` also returns faults like this for each failed call.
+pub fn fault_error(fault: &XmlRpcValue) -> ClientError {
+    let code = fault.get("faultCode").and_then(XmlRpcValue::as_i64).unwrap_or_default();
+    let message = fault.get("faultString").and_then(XmlRpcValue::as_str).unwrap_or("unknown fault");
+
+    ClientError::Rpc(format!("{} (fault code {})", message, code))
+}
+
+fn invalid(message: String) -> ClientError {
+    ClientError::XmlRpc(message)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> ClientResult<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(';').ok_or_else(|| invalid(format!("unterminated entity in {:?}", text)))?;
+        let entity = &rest[1..end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| invalid(format!("unknown entity &{};", entity)))?
+            },
+        };
+
+        out.push(c);
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    closing: bool,
+    /// The tag closed itself, ex: `<nil/>`.
+    empty: bool,
+}
+
+/// A minimal parser for the subset of xml used by xml-rpc responses.
+struct Parser<'a> {
+    xml: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(xml: &'a str) -> Self {
+        Self {
+            xml,
+            pos: 0,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.xml[self.pos..]
+    }
+
+    /// Read the text up to the next tag.
+    fn text(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find('<').unwrap_or(rest.len());
+        self.pos += end;
+
+        &rest[..end]
+    }
+
+    /// Read the next tag, skipping whitespace, the xml declaration and comments.
+    fn tag(&mut self) -> ClientResult<Tag<'a>> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            let skip_until = if trimmed.starts_with("<?") {
+                Some("?>")
+            } else if trimmed.starts_with("<!--") {
+                Some("-->")
+            } else {
+                None
+            };
+
+            if let Some(terminator) = skip_until {
+                let end = trimmed.find(terminator)
+                    .ok_or_else(|| invalid(String::from("unterminated xml declaration or comment")))?;
+                self.pos += end + terminator.len();
+                continue;
+            }
+
+            if !trimmed.starts_with('<') {
+                return Err(invalid(format!("expected a tag at byte {}", self.pos)));
+            }
+
+            let end = trimmed.find('>')
+                .ok_or_else(|| invalid(format!("unterminated tag at byte {}", self.pos)))?;
+            let inner = &trimmed[1..end];
+            self.pos += end + 1;
+
+            let closing = inner.starts_with('/');
+            let empty = inner.ends_with('/');
+            let name = inner.trim_matches('/')
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+
+            return Ok(Tag {
+                name,
+                closing,
+                empty,
+            });
+        }
+    }
+
+    fn peek_tag(&mut self) -> ClientResult<Tag<'a>> {
+        let pos = self.pos;
+        let tag = self.tag();
+        self.pos = pos;
+
+        tag
+    }
+
+    fn open(&mut self, name: &str) -> ClientResult<Tag<'a>> {
+        let tag = self.tag()?;
+        if tag.closing || tag.name != name {
+            return Err(invalid(format!("expected <{}>, found <{}>", name, tag.name)));
+        }
+
+        Ok(tag)
+    }
+
+    fn close(&mut self, name: &str) -> ClientResult<()> {
+        let tag = self.tag()?;
+        if !tag.closing || tag.name != name {
+            return Err(invalid(format!("expected </{}>, found <{}>", name, tag.name)));
+        }
+
+        Ok(())
+    }
+
+    /// Read a `<value>`. A value without a type is a string.
+    fn value(&mut self) -> ClientResult<XmlRpcValue> {
+        if self.open("value")?.empty {
+            return Ok(XmlRpcValue::String(String::new()));
+        }
+
+        let text = self.text();
+        let tag = self.tag()?;
+        if tag.closing {
+            return match tag.name {
+                "value" => Ok(XmlRpcValue::String(unescape(text)?)),
+                name => Err(invalid(format!("expected </value>, found </{}>", name))),
+            };
+        }
+
+        let value = self.typed_value(tag)?;
+        self.close("value")?;
+
+        Ok(value)
+    }
+
+    fn typed_value(&mut self, tag: Tag<'a>) -> ClientResult<XmlRpcValue> {
+        match tag.name {
+            "array" => {
+                let mut values = vec![];
+                if tag.empty {
+                    return Ok(XmlRpcValue::Array(values));
+                }
+
+                if !self.open("data")?.empty {
+                    while !self.peek_tag()?.closing {
+                        values.push(self.value()?);
+                    }
+                    self.close("data")?;
+                }
+                self.close("array")?;
+
+                Ok(XmlRpcValue::Array(values))
+            },
+            "struct" => {
+                let mut members = BTreeMap::new();
+                if tag.empty {
+                    return Ok(XmlRpcValue::Struct(members));
+                }
+
+                while !self.peek_tag()?.closing {
+                    self.open("member")?;
+                    self.open("name")?;
+                    let name = unescape(self.text())?;
+                    self.close("name")?;
+                    let value = self.value()?;
+                    self.close("member")?;
+
+                    members.insert(name, value);
+                }
+                self.close("struct")?;
+
+                Ok(XmlRpcValue::Struct(members))
+            },
+            "nil" => {
+                if !tag.empty {
+                    self.close("nil")?;
+                }
+
+                Ok(XmlRpcValue::Nil)
+            },
+            name => {
+                let text = if tag.empty {
+                    ""
+                } else {
+                    let text = self.text();
+                    self.close(name)?;
+                    text
+                };
+
+                let value = match name {
+                    "string" => XmlRpcValue::String(unescape(text)?),
+                    "i1" | "i2" | "i4" | "i8" | "int" => XmlRpcValue::Int(text.trim().parse()
+                        .map_err(|_| invalid(format!("invalid int {:?}", text)))?),
+                    "boolean" => XmlRpcValue::Bool(text.trim() == "1"),
+                    "double" => XmlRpcValue::Double(text.trim().parse()
+                        .map_err(|_| invalid(format!("invalid double {:?}", text)))?),
+                    "base64" => {
+                        let encoded: String = text.split_whitespace().collect();
+                        XmlRpcValue::Base64(base64::decode(encoded)
+                            .map_err(|e| invalid(format!("invalid base64: {}", e)))?)
+                    },
+                    _ => return Err(invalid(format!("unknown value type <{}>", name))),
+                };
+
+                Ok(value)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encode a value, and decode it from a response like a server would send it back.
+    fn round_trip(value: &XmlRpcValue) -> XmlRpcValue {
+        let mut xml = String::from(r#"<?xml version="1.0"?><methodResponse><params><param>"#);
+        encode_value(value, &mut xml);
+        xml.push_str("</param></params></methodResponse>");
+
+        decode_response(&xml).unwrap()
+    }
+
+    #[test]
+    fn round_trips_values() {
+        let mut members = BTreeMap::new();
+        members.insert(String::from("small"), XmlRpcValue::Int(-5));
+        members.insert(String::from("large"), XmlRpcValue::Int(1 << 40));
+        members.insert(String::from("text"), XmlRpcValue::from("<a & b>"));
+        members.insert(String::from("data"), XmlRpcValue::Base64(vec![0, 159, 255, 10]));
+        members.insert(String::from("list"), XmlRpcValue::Array(vec![
+            XmlRpcValue::Bool(true),
+            XmlRpcValue::Double(1.5),
+            XmlRpcValue::Array(vec![]),
+            XmlRpcValue::Nil,
+        ]));
+        members.insert(String::from("empty"), XmlRpcValue::Struct(BTreeMap::new()));
+        let value = XmlRpcValue::Struct(members);
+
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn encodes_ints_by_size() {
+        let xml = encode_call("d.multicall2", &[XmlRpcValue::Int(42), XmlRpcValue::Int(i64::from(i32::MAX) + 1)]);
+
+        assert!(xml.contains("<methodName>d.multicall2</methodName>"));
+        assert!(xml.contains("<value><i4>42</i4></value>"));
+        assert!(xml.contains("<value><i8>2147483648</i8></value>"));
+    }
+
+    #[test]
+    fn decodes_other_value_forms() {
+        let xml = r#"<?xml version="1.0"?>
+            <!-- rTorrent doesn't send comments, other servers may. -->
+            <methodResponse>
+              <params>
+                <param>
+                  <value><array><data>
+                    <value>untyped &#x41;&#66;</value>
+                    <value/>
+                    <value><int>7</int></value>
+                    <value><i8>-9000000000</i8></value>
+                    <value><base64>
+                      AAEC
+                    </base64></value>
+                    <value><string/></value>
+                  </data></array></value>
+                </param>
+              </params>
+            </methodResponse>"#;
+
+        assert_eq!(decode_response(xml).unwrap(), XmlRpcValue::Array(vec![
+            XmlRpcValue::from("untyped AB"),
+            XmlRpcValue::from(""),
+            XmlRpcValue::Int(7),
+            XmlRpcValue::Int(-9000000000),
+            XmlRpcValue::Base64(vec![0, 1, 2]),
+            XmlRpcValue::from(""),
+        ]));
+    }
+
+    #[test]
+    fn decodes_empty_params() {
+        assert_eq!(decode_response("<methodResponse><params/></methodResponse>").unwrap(), XmlRpcValue::Nil);
+        assert_eq!(decode_response("<methodResponse><params></params></methodResponse>").unwrap(), XmlRpcValue::Nil);
+    }
+
+    #[test]
+    fn decodes_faults() {
+        let xml = "<methodResponse><fault><value><struct>
+            <member><name>faultCode</name><value><i4>-501</i4></value></member>
+            <member><name>faultString</name><value><string>Could not find info-hash.</string></value></member>
+            </struct></value></fault></methodResponse>";
+
+        let err = decode_response(xml).unwrap_err();
+        assert!(matches!(err, ClientError::Rpc(message) if message == "Could not find info-hash. (fault code -501)"));
+    }
+
+    #[test]
+    fn rejects_invalid_responses() {
+        let invalid = [
+            "<methodCall></methodCall>",
+            "<methodResponse><params><param><value><i4>x</i4></value></param></params></methodResponse>",
+            "<methodResponse><params><param><value><date>1</date></value></param></params></methodResponse>",
+            "<methodResponse><params><param><value>&bogus;</value></param></params></methodResponse>",
+            "<methodResponse><params><param><value><string>unclosed",
+        ];
+
+        for xml in invalid {
+            assert!(matches!(decode_response(xml), Err(ClientError::XmlRpc(_))), "{} was accepted", xml);
+        }
+    }
+}
@@ -22,10 +22,10 @@ impl TorznabClient {
     }
 
     /// Construct a new client without getting the capabilities
-    pub fn new_no_capabilities(name: String, base_url: &str, api_key: &str) -> Self {
+    pub fn new_no_capabilities(name: String, base_url: &str, api_key: &str, http: reqwest::Client) -> Self {
         TorznabClient {
             name: name.clone(),
-            http: reqwest::Client::new(),
+            http,
             base_url: base_url.to_string(),
             api_key: api_key.to_string(),
             capabilities: Capabilities::default(),
@@ -34,10 +34,13 @@ impl TorznabClient {
     }
 
     /// Construct a new client and get the capabilities.
-    pub async fn new(name: String, base_url: &str, api_key: &str) -> Result<Self, reqwest::Error> {
+    ///
+    /// The http client is used for all requests to the indexer, including
+    /// downloading torrent files, so any proxy should be configured on it.
+    pub async fn new(name: String, base_url: &str, api_key: &str, http: reqwest::Client) -> Result<Self, reqwest::Error> {
         let mut client = TorznabClient {
             name: name.clone(),
-            http: reqwest::Client::new(),
+            http,
             base_url: base_url.to_string(),
             api_key: api_key.to_string(),
             capabilities: Capabilities::default(),
@@ -50,6 +53,11 @@ impl TorznabClient {
         Ok(client)
     }
 
+    /// The http client used for requests to the indexer.
+    pub fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
     /// Send a request to the indexer using the query parameters.
     async fn request(&self, param_str: String) -> Result<Bytes, reqwest::Error> {
         let span = span!(parent: &self.client_span, Level::INFO, "client request");
@@ -82,6 +90,22 @@ impl TorznabClient {
 
     /// Search for torrents.
     pub async fn search(&self, func: SearchFunction, generic_params: GenericSearchParameters) -> Result<Vec<TorrentResult>, ClientError> {
+        let metrics = &crate::metrics::METRICS;
+        metrics.indexer_searches.with_label_values(&[&self.name]).inc();
+
+        let timer = metrics.indexer_latency.with_label_values(&[&self.name]).start_timer();
+        let result = self.search_results(func, generic_params).await;
+        timer.observe_duration();
+
+        match &result {
+            Ok(torrents) => metrics.indexer_results.with_label_values(&[&self.name]).inc_by(torrents.len() as u64),
+            Err(_) => metrics.indexer_errors.with_label_values(&[&self.name]).inc(),
+        }
+
+        result
+    }
+
+    async fn search_results(&self, func: SearchFunction, generic_params: GenericSearchParameters) -> Result<Vec<TorrentResult>, ClientError> {
         let param_str = format!("{}{}", func.to_params(), generic_params.to_params());
 
         let bytes = self.request(param_str).await?;
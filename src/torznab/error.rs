@@ -4,6 +4,7 @@ pub enum ClientError {
     SearchResultError(super::ResultError),
     InvalidRedirect,
     TorrentError(lava_torrent::LavaTorrentError),
+    ProxyError(crate::config::ProxyError),
 }
 
 impl From<reqwest::Error> for ClientError {
@@ -22,4 +23,10 @@ impl From<lava_torrent::LavaTorrentError> for ClientError {
     fn from(e: lava_torrent::LavaTorrentError) -> Self {
         ClientError::TorrentError(e)
     }
+}
+
+impl From<crate::config::ProxyError> for ClientError {
+    fn from(e: crate::config::ProxyError) -> Self {
+        ClientError::ProxyError(e)
+    }
 }
\ No newline at end of file
@@ -16,25 +16,41 @@ pub enum ResultError {
 pub struct TorrentResult {
     pub name: String,
     pub link: String,
-    /* size: u64,
-    categories: Vec<u32>, */
+    /// Total size of the torrent in bytes, if the indexer reported it.
+    pub size: Option<u64>,
+    /* categories: Vec<u32>, */
 }
 
 impl TorrentResult {
     pub fn from_item(item: Item) -> Result<Self, ResultError> {
         let name = item.title().ok_or(ResultError::MissingTitle)?;
         let link = item.link().ok_or(ResultError::MissingLink)?;
-        /* let size = item.enclosure().map(|e| e.length().parse::<u64>());
-        let categories = item.categories().ok_or(ResultError::MissingTitle)?; */
+        let size = Self::item_size(&item);
+        /* let categories = item.categories().ok_or(ResultError::MissingTitle)?; */
 
         Ok(TorrentResult {
             name: String::from(name.clone()),
             link: String::from(link),
-            /* size,
-            categories, */
+            size,
+            /* categories, */
         })
     }
 
+    /// Get the size of the item from the `torznab:attr` size attribute, falling back
+    /// to the length of the enclosure.
+    fn item_size(item: &Item) -> Option<u64> {
+        let attr_size = item.extensions().get("torznab")
+            .and_then(|ext| ext.get("attr"))
+            .and_then(|attrs| attrs.iter()
+                .find(|attr| attr.attrs().get("name").map(|n| n.as_str()) == Some("size")))
+            .and_then(|attr| attr.attrs().get("value"))
+            .and_then(|value| value.parse::<u64>().ok());
+
+        attr_size.or_else(|| item.enclosure()
+            .and_then(|e| e.length().parse::<u64>().ok())
+            .filter(|size| *size > 0))
+    }
+
     #[async_recursion]
     async fn download_impl(&self, client: &reqwest::Client, url: &str) -> Result<Torrent, ClientError> {
         let res = client
@@ -65,8 +81,9 @@ impl TorrentResult {
         }
     }
 
-    pub async fn download_torrent(&self) -> Result<Torrent, ClientError> {
-        self.download_impl(&reqwest::Client::default(), &self.link).await
+    /// Download the torrent file using the http client of the indexer it was found on.
+    pub async fn download_torrent(&self, client: &reqwest::Client) -> Result<Torrent, ClientError> {
+        self.download_impl(client, &self.link).await
     }
 }
 
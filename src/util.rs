@@ -1,3 +1,48 @@
+use crate::config::{ProxyConfig, ProxyError};
+
 pub fn bool_true() -> bool {
     true
-}
\ No newline at end of file
+}
+
+/// Build an http client that routes its requests through the proxy, if one is given.
+pub fn http_client(proxy: Option<&ProxyConfig>, no_proxy: &[String]) -> Result<reqwest::Client, ProxyError> {
+    Ok(http_client_builder(proxy, no_proxy)?.build()?)
+}
+
+/// Start building an http client that routes its requests through the proxy, for clients that need more
+/// settings, like a cookie store.
+pub fn http_client_builder(proxy: Option<&ProxyConfig>, no_proxy: &[String]) -> Result<reqwest::ClientBuilder, ProxyError> {
+    let mut builder = reqwest::Client::builder();
+
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy.to_reqwest_proxy(no_proxy)?);
+    }
+
+    Ok(builder)
+}
+
+/// The current time as seconds since the unix epoch.
+pub fn unix_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Format a size in bytes in binary units, ex: `1.50 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}